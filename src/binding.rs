@@ -0,0 +1,260 @@
+use std::ops::Range;
+
+use crate::util::HashSet;
+use crate::*;
+
+/** A [`Language`] with names that can be bound.
+
+In a language with binders, like the lambda calculus, names are
+e-classes (usually holding a single symbol like `x`).
+A use of a name, like `(var x)`, is an e-node whose
+[`var_name`](BindingLanguage::var_name()) is the e-class of `x`.
+A binder, like `(lam x body)`, is an e-node whose
+[`binder`](BindingLanguage::binder()) is the e-class of `x` together
+with the children it is bound in.
+
+This is all `egg` needs to track the names free in each e-class with
+[`FreeVars`], and to push substitutions under binders without
+capturing names with [`CaptureAvoid`].
+
+```
+use egg::{rewrite as rw, *};
+use std::ops::Range;
+
+define_language! {
+    enum Lambda {
+        "var" = Var(Id),
+        "app" = App([Id; 2]),
+        "lam" = Lam([Id; 2]),
+        "let" = Let([Id; 3]),
+        Symbol(Symbol),
+    }
+}
+
+impl BindingLanguage for Lambda {
+    fn var_name(&self) -> Option<Id> {
+        match self {
+            Lambda::Var(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn binder(&self) -> Option<(Id, Range<usize>)> {
+        match self {
+            Lambda::Lam([v, _]) => Some((*v, 1..2)),
+            Lambda::Let([v, _, _]) => Some((*v, 2..3)),
+            _ => None,
+        }
+    }
+
+    fn fresh_name(id: Id) -> Self {
+        Lambda::Symbol(format!("_{}", id).into())
+    }
+}
+
+#[derive(Default)]
+struct Free;
+impl Analysis<Lambda> for Free {
+    type Data = FreeVars;
+    fn make(egraph: &mut EGraph<Lambda, Self>, enode: &Lambda) -> FreeVars {
+        FreeVars::make(egraph, enode)
+    }
+    fn merge(&mut self, to: &mut FreeVars, from: FreeVars) -> DidMerge {
+        to.merge(from)
+    }
+}
+
+impl BindingAnalysis<Lambda> for Free {
+    fn free_vars(data: &FreeVars) -> &FreeVars {
+        data
+    }
+}
+
+let different = |v1: &str, v2: &str| {
+    let (v1, v2): (Var, Var) = (v1.parse().unwrap(), v2.parse().unwrap());
+    move |egraph: &mut EGraph<Lambda, Free>, _, subst: &Subst| {
+        egraph.find(subst[v1]) != egraph.find(subst[v2])
+    }
+};
+
+let rules: Vec<Rewrite<Lambda, Free>> = vec![
+    rw!("beta"; "(app (lam ?v ?body) ?e)" => "(let ?v ?e ?body)"),
+    rw!("let-app"; "(let ?v ?e (app ?a ?b))" => "(app (let ?v ?e ?a) (let ?v ?e ?b))"),
+    rw!("let-var-same"; "(let ?v ?e (var ?v))" => "?e"),
+    rw!("let-var-diff"; "(let ?v1 ?e (var ?v2))" => "(var ?v2)"
+        if different("?v1", "?v2")),
+    rw!("let-lam-same"; "(let ?v ?e (lam ?v ?body))" => "(lam ?v ?body)"),
+    rw!("let-lam-diff"; "(let ?v1 ?e (lam ?v2 ?body))" =>
+        { CaptureAvoid {
+            fresh: "?fresh".parse().unwrap(),
+            name: "?v2".parse().unwrap(),
+            value: "?e".parse().unwrap(),
+            if_not_free: "(lam ?v2 (let ?v1 ?e ?body))".parse().unwrap(),
+            if_free: "(lam ?fresh (let ?v1 ?e (let ?v2 (var ?fresh) ?body)))"
+                .parse()
+                .unwrap(),
+        }}
+        if different("?v1", "?v2")),
+];
+
+// the free x must not be captured by the lambda
+let start = "(app (lam y (lam x (var y))) (var x))".parse().unwrap();
+let runner = Runner::default().with_expr(&start).run(&rules);
+let captured = "(lam x (var x))".parse().unwrap();
+assert!(runner.egraph.lookup_expr(&captured).is_none());
+let renamed: Pattern<Lambda> = "(lam ?fresh (var x))".parse().unwrap();
+assert!(renamed.search_eclass(&runner.egraph, runner.roots[0]).is_some());
+```
+**/
+pub trait BindingLanguage: Language {
+    /// If this e-node is a use of a name, returns the e-class of that name.
+    fn var_name(&self) -> Option<Id>;
+
+    /// If this e-node binds a name, returns the e-class of that name and
+    /// the indices of the children it is bound in.
+    fn binder(&self) -> Option<(Id, Range<usize>)>;
+
+    /// Makes a leaf for a name that is fresh for the e-class `id`.
+    ///
+    /// [`CaptureAvoid`] renames a binder in `id` to this name, so it
+    /// should not clash with names in the input; deriving it from `id`
+    /// means rewriting the same e-class again reuses the same name.
+    fn fresh_name(id: Id) -> Self;
+}
+
+/// The names that may be free in an e-class of a [`BindingLanguage`].
+///
+/// Put this in the [`Analysis::Data`] and implement [`BindingAnalysis`]
+/// so [`CaptureAvoid`] can find it.
+/// Names are compared by the [`Id`]s of their e-classes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FreeVars(HashSet<Id>);
+
+impl FreeVars {
+    /// Computes the free names of `enode` from the free names of its
+    /// children, for use in [`Analysis::make`].
+    pub fn make<L, N>(egraph: &EGraph<L, N>, enode: &L) -> Self
+    where
+        L: BindingLanguage,
+        N: BindingAnalysis<L>,
+    {
+        let (bound, scope) = match enode.binder() {
+            Some((name, scope)) => (Some(name), scope),
+            None => (None, 0..0),
+        };
+        let mut free = HashSet::default();
+        free.extend(enode.var_name());
+        for (i, &child) in enode.children().iter().enumerate() {
+            let child_free = N::free_vars(&egraph[child].data).iter();
+            if scope.contains(&i) {
+                free.extend(child_free.filter(|&name| Some(name) != bound));
+            } else {
+                free.extend(child_free);
+            }
+        }
+        FreeVars(free)
+    }
+
+    /// Merges the free names of two equivalent e-classes, for use in
+    /// [`Analysis::merge`].
+    ///
+    /// This keeps only the names free in both, since equivalent terms
+    /// may mention different names, like `(* 0 (var x))` and `0`.
+    pub fn merge(&mut self, other: FreeVars) -> DidMerge {
+        let before = self.0.len();
+        self.0.retain(|name| other.0.contains(name));
+        DidMerge(before != self.0.len(), self.0.len() != other.0.len())
+    }
+
+    /// Returns `true` if the name with e-class `name` may be free.
+    pub fn contains(&self, name: Id) -> bool {
+        self.0.contains(&name)
+    }
+
+    /// Iterates over the e-classes of the free names.
+    pub fn iter(&self) -> impl Iterator<Item = Id> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Returns the number of free names.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if there are no free names.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// An [`Analysis`] that tracks the [`FreeVars`] of each e-class.
+/// See [`BindingLanguage`] for an example.
+pub trait BindingAnalysis<L: BindingLanguage>: Analysis<L> {
+    /// Gets the free names out of the data of an e-class.
+    fn free_vars(data: &Self::Data) -> &FreeVars;
+}
+
+/// An [`Applier`] that pushes a substitution under a binder without
+/// capturing names.
+///
+/// If the name bound to `name` is free in the e-class bound to `value`,
+/// this binds `fresh` to a [`fresh_name`] and applies `if_free`, which
+/// should rename the binder to `fresh`.
+/// Otherwise, it applies `if_not_free`.
+/// See [`BindingLanguage`] for an example.
+///
+/// [`fresh_name`]: BindingLanguage::fresh_name()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureAvoid<L> {
+    /// The variable to bind to the fresh name in `if_free`.
+    pub fresh: Var,
+    /// The variable bound to the name of the binder.
+    pub name: Var,
+    /// The variable bound to the value being substituted.
+    pub value: Var,
+    /// The pattern to apply if `name` is not free in `value`.
+    pub if_not_free: Pattern<L>,
+    /// The pattern to apply if `name` is free in `value`.
+    pub if_free: Pattern<L>,
+}
+
+impl<L, N> Applier<L, N> for CaptureAvoid<L>
+where
+    L: BindingLanguage,
+    N: BindingAnalysis<L>,
+{
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<L>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        let name = egraph.find(subst[self.name]);
+        let free = N::free_vars(&egraph[subst[self.value]].data);
+        if free.contains(name) {
+            let mut subst = subst.clone();
+            let fresh = egraph.add(L::fresh_name(eclass));
+            subst.insert(self.fresh, fresh);
+            self.if_free
+                .apply_one(egraph, eclass, &subst, searcher_ast, rule_name)
+        } else {
+            self.if_not_free
+                .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
+        }
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        let mut vars = vec![self.name, self.value];
+        let applied = Applier::<L, N>::vars(&self.if_not_free)
+            .into_iter()
+            .chain(Applier::<L, N>::vars(&self.if_free));
+        for v in applied {
+            if v != self.fresh && !vars.contains(&v) {
+                vars.push(v);
+            }
+        }
+        vars
+    }
+}
@@ -1,7 +1,8 @@
 /*!
 EGraph visualization with [GraphViz]
 
-Use the [`Dot`] struct to visualize an [`EGraph`]
+Use the [`Dot`] struct to visualize an [`EGraph`],
+and [`RuleGraphDot`] to visualize a [`RuleGraph`].
 
 [GraphViz]: https://graphviz.gitlab.io/
 !*/
@@ -11,7 +12,7 @@ use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{Error, ErrorKind, Result, Write};
 use std::path::Path;
 
-use crate::{egraph::EGraph, Analysis, Language};
+use crate::{egraph::EGraph, Analysis, Language, RuleGraph};
 
 /**
 A wrapper for an [`EGraph`] that can output [GraphViz] for
@@ -67,8 +68,7 @@ where
     /// Writes the `Dot` to a .dot file with the given filename.
     /// Does _not_ require a `dot` binary.
     pub fn to_dot(&self, filename: impl AsRef<Path>) -> Result<()> {
-        let mut file = std::fs::File::create(filename)?;
-        write!(file, "{}", self)
+        write_dot(self, filename)
     }
 
     /// Adds a line to the dot output.
@@ -130,25 +130,7 @@ where
         S2: AsRef<OsStr>,
         I: IntoIterator<Item = S2>,
     {
-        use std::process::{Command, Stdio};
-        let mut child = Command::new(program)
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())
-            .spawn()?;
-        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
-        write!(stdin, "{}", self)?;
-        match child.wait()?.code() {
-            Some(0) => Ok(()),
-            Some(e) => Err(Error::new(
-                ErrorKind::Other,
-                format!("dot program returned error code {}", e),
-            )),
-            None => Err(Error::new(
-                ErrorKind::Other,
-                "dot program was killed by a signal",
-            )),
-        }
+        run_dot(self, program, args)
     }
 
     // gives back the appropriate label and anchor
@@ -170,6 +152,40 @@ where
     }
 }
 
+/// Writes `dot` to a .dot file with the given filename.
+fn write_dot(dot: &impl Display, filename: impl AsRef<Path>) -> Result<()> {
+    let mut file = std::fs::File::create(filename)?;
+    write!(file, "{}", dot)
+}
+
+/// Invokes some program with the given arguments, piping `dot` into stdin.
+fn run_dot<S1, S2, I>(dot: &impl Display, program: S1, args: I) -> Result<()>
+where
+    S1: AsRef<OsStr>,
+    S2: AsRef<OsStr>,
+    I: IntoIterator<Item = S2>,
+{
+    use std::process::{Command, Stdio};
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    write!(stdin, "{}", dot)?;
+    match child.wait()?.code() {
+        Some(0) => Ok(()),
+        Some(e) => Err(Error::new(
+            ErrorKind::Other,
+            format!("dot program returned error code {}", e),
+        )),
+        None => Err(Error::new(
+            ErrorKind::Other,
+            "dot program was killed by a signal",
+        )),
+    }
+}
+
 impl<'a, L: Language, N: Analysis<L>> Debug for Dot<'a, L, N> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_tuple("Dot").field(self.egraph).finish()
@@ -234,3 +250,93 @@ where
         write!(f, "}}")
     }
 }
+
+/**
+A wrapper for a [`RuleGraph`] that can output [GraphViz] for
+visualization.
+
+The [`RuleGraph::dot`](RuleGraph::dot()) method creates
+`RuleGraphDot`s, which can be rendered just like [`Dot`]s.
+Each rule is a node, with an edge to each rule it can enable.
+
+[GraphViz]: https://graphviz.gitlab.io/
+**/
+#[derive(Debug)]
+pub struct RuleGraphDot<'a> {
+    pub(crate) graph: &'a RuleGraph,
+    /// A list of strings to be output top part of the dot file.
+    pub config: Vec<String>,
+}
+
+impl RuleGraphDot<'_> {
+    /// Writes the `RuleGraphDot` to a .dot file with the given filename.
+    /// Does _not_ require a `dot` binary.
+    pub fn to_dot(&self, filename: impl AsRef<Path>) -> Result<()> {
+        write_dot(self, filename)
+    }
+
+    /// Adds a line to the dot output.
+    /// Indentation and a newline will be added automatically.
+    pub fn with_config_line(mut self, line: impl Into<String>) -> Self {
+        self.config.push(line.into());
+        self
+    }
+
+    /// Renders the `RuleGraphDot` to a .png file with the given filename.
+    /// Requires a `dot` binary to be on your `$PATH`.
+    pub fn to_png(&self, filename: impl AsRef<Path>) -> Result<()> {
+        self.run_dot(["-Tpng".as_ref(), "-o".as_ref(), filename.as_ref()])
+    }
+
+    /// Renders the `RuleGraphDot` to a .svg file with the given filename.
+    /// Requires a `dot` binary to be on your `$PATH`.
+    pub fn to_svg(&self, filename: impl AsRef<Path>) -> Result<()> {
+        self.run_dot(["-Tsvg".as_ref(), "-o".as_ref(), filename.as_ref()])
+    }
+
+    /// Renders the `RuleGraphDot` to a .pdf file with the given filename.
+    /// Requires a `dot` binary to be on your `$PATH`.
+    pub fn to_pdf(&self, filename: impl AsRef<Path>) -> Result<()> {
+        self.run_dot(["-Tpdf".as_ref(), "-o".as_ref(), filename.as_ref()])
+    }
+
+    /// Invokes `dot` with the given arguments, piping this formatted
+    /// `RuleGraphDot` into stdin.
+    pub fn run_dot<S, I>(&self, args: I) -> Result<()>
+    where
+        S: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+    {
+        self.run("dot", args)
+    }
+
+    /// Invokes some program with the given arguments, piping this
+    /// formatted `RuleGraphDot` into stdin.
+    pub fn run<S1, S2, I>(&self, program: S1, args: I) -> Result<()>
+    where
+        S1: AsRef<OsStr>,
+        S2: AsRef<OsStr>,
+        I: IntoIterator<Item = S2>,
+    {
+        run_dot(self, program, args)
+    }
+}
+
+impl Display for RuleGraphDot<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "digraph rules {{")?;
+
+        for line in &self.config {
+            writeln!(f, "  {}", line)?;
+        }
+
+        for (i, rule) in self.graph.rules.iter().enumerate() {
+            writeln!(f, "  {}[label = {:?}]", i, rule.as_str())?;
+        }
+        for (a, b) in &self.graph.edges {
+            writeln!(f, "  {} -> {}", a, b)?;
+        }
+
+        write!(f, "}}")
+    }
+}
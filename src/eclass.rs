@@ -19,6 +19,10 @@ pub struct EClass<L, D> {
     pub data: D,
     /// The original Ids of parent enodes.
     pub(crate) parents: Vec<Id>,
+    /// The [`generation`](EGraph::generation()) in which this eclass was
+    /// last added to, merged into, or had its data changed.
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    pub(crate) touched: usize,
 }
 
 impl<L, D> EClass<L, D> {
@@ -3,6 +3,7 @@ use std::{
     borrow::BorrowMut,
     fmt::{self, Debug, Display},
     marker::PhantomData,
+    sync::{Arc, Mutex},
 };
 
 #[cfg(feature = "serde-1")]
@@ -85,6 +86,33 @@ pub struct EGraph<L: Language, N: Analysis<L>> {
     /// Only manually set it if you know what you're doing.
     #[cfg_attr(feature = "serde-1", serde(skip))]
     pub clean: bool,
+    /// The number of times this egraph has been rebuilt, see
+    /// [`EGraph::generation`].
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    generation: usize,
+    /// The guards pattern variables can check, see [`EGraph::with_guard`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    guards: HashMap<Symbol, Guard<N::Data>>,
+    /// The guards that were used without being registered, so each is only
+    /// warned about once.
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    unknown_guards: UnknownGuards,
+    /// Where unions are logged while a [`Runner`] with an event log runs
+    /// an iteration.
+    #[cfg(feature = "reports")]
+    #[serde(skip)]
+    pub(crate) event_log: Option<EventLog>,
+}
+
+type Guard<D> = Arc<dyn Fn(&D) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct UnknownGuards(Mutex<HashSet<Symbol>>);
+
+impl Clone for UnknownGuards {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().unwrap().clone()))
+    }
 }
 
 #[cfg(feature = "serde-1")]
@@ -117,11 +145,16 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             unionfind: Default::default(),
             nodes: Default::default(),
             clean: false,
+            generation: 0,
             explain: None,
             pending: Default::default(),
             memo: Default::default(),
             analysis_pending: Default::default(),
             classes_by_op: Default::default(),
+            guards: Default::default(),
+            unknown_guards: Default::default(),
+            #[cfg(feature = "reports")]
+            event_log: None,
         }
     }
 
@@ -194,6 +227,71 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.classes.len()
     }
 
+    /// Register a guard that pattern variables can check during e-matching.
+    ///
+    /// A pattern variable written `?x:name` only matches e-classes whose
+    /// [`Analysis::Data`] passes the guard registered as `name`.
+    /// Unlike a [`Condition`], which only runs once a match has been
+    /// found, guards are checked as soon as the variable is bound, so
+    /// they prune the search.
+    /// A guard that is not registered matches nothing, and logs a
+    /// warning the first time it is checked.
+    /// Only variables can carry guards; patterns have no operator
+    /// variables, so there is nothing to guard in operator position.
+    ///
+    /// Guards are not serialized, and [`LanguageMapper::map_egraph`] drops
+    /// them since they read the old analysis data, so register them again
+    /// after deserializing or mapping an egraph.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// #[derive(Default)]
+    /// struct IsConst;
+    /// impl Analysis<SymbolLang> for IsConst {
+    ///     type Data = bool;
+    ///     fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang) -> bool {
+    ///         enode.op.as_str().parse::<i32>().is_ok()
+    ///     }
+    ///     fn merge(&mut self, a: &mut bool, b: bool) -> DidMerge {
+    ///         merge_max(a, b)
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::new(IsConst).with_guard("const", |is_const: &bool| *is_const);
+    /// egraph.add_expr(&"(+ x 1)".parse().unwrap());
+    /// egraph.add_expr(&"(+ 2 3)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let any: Pattern<SymbolLang> = "(+ ?a ?b)".parse().unwrap();
+    /// let consts: Pattern<SymbolLang> = "(+ ?a:const ?b:const)".parse().unwrap();
+    /// assert_eq!(any.n_matches(&egraph), 2);
+    /// assert_eq!(consts.n_matches(&egraph), 1);
+    /// ```
+    pub fn with_guard(
+        mut self,
+        name: impl Into<Symbol>,
+        guard: impl Fn(&N::Data) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.guards.insert(name.into(), Arc::new(guard));
+        self
+    }
+
+    /// Whether the eclass `id` passes the guard registered as `name`.
+    pub(crate) fn check_guard(&self, name: Symbol, id: Id) -> bool {
+        match self.guards.get(&name) {
+            Some(guard) => guard(&self[id].data),
+            None => {
+                if self.unknown_guards.0.lock().unwrap().insert(name) {
+                    warn!(
+                        "No guard named `{}` was registered, so it matches nothing",
+                        name
+                    );
+                }
+                false
+            }
+        }
+    }
+
     /// Enable explanations for this `EGraph`.
     /// This allows the egraph to explain why two expressions are
     /// equivalent with the [`explain_equivalence`](EGraph::explain_equivalence) function.
@@ -616,10 +714,14 @@ where
                 .collect(),
             data: self.map_data(src_eclass.data),
             parents: src_eclass.parents,
+            touched: src_eclass.touched,
         }
     }
 
     /// Map an `EGraph` over `L` into an `EGraph` over `L2`.
+    ///
+    /// The guards registered with [`EGraph::with_guard`] are dropped,
+    /// since they read `A::Data`.
     fn map_egraph(&self, src_egraph: EGraph<L, A>) -> EGraph<Self::L2, Self::A2> {
         let kv_map = |(k, v): (L, Id)| (self.map_node(k), v);
         EGraph {
@@ -645,6 +747,12 @@ where
                 .map(|(k, v)| (self.map_discriminant(k), v))
                 .collect(),
             clean: src_egraph.clean,
+            generation: src_egraph.generation,
+            // the guards are for the old analysis data
+            guards: Default::default(),
+            unknown_guards: Default::default(),
+            #[cfg(feature = "reports")]
+            event_log: None,
         }
     }
 }
@@ -859,13 +967,18 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         let mut new_node_q = Vec::with_capacity(pat.len());
         for node in pat {
             match node {
-                ENodeOrVar::Var(var) => {
+                ENodeOrVar::Var(var) | ENodeOrVar::Guarded(var, _) | ENodeOrVar::Leaf(var, _) => {
                     let id = self.find(subst[*var]);
                     new_ids.push(id);
                     new_node_q.push(false);
                 }
+                ENodeOrVar::Seq(_) => {
+                    // only used by the enode it is a child of
+                    new_ids.push(Id::from(0));
+                    new_node_q.push(false);
+                }
                 ENodeOrVar::ENode(node) => {
-                    let new_node = node.clone().map_children(|i| new_ids[usize::from(i)]);
+                    let new_node = pattern::instantiate_node(pat.as_ref(), node, &new_ids, subst);
                     let size_before = self.unionfind.size();
                     let next_id = self.add_uncanonical(new_node);
                     if self.unionfind.size() > size_before {
@@ -913,6 +1026,13 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.lookup_internal(enode).map(|id| self.find(id))
     }
 
+    /// The enode `Id` of a childless enode in the egraph, so that
+    /// [`id_to_node`](EGraph::id_to_node) gives back `leaf`.
+    pub(crate) fn leaf_id(&self, leaf: &L) -> Id {
+        debug_assert!(leaf.is_leaf());
+        self.memo[leaf]
+    }
+
     fn lookup_internal<B>(&self, mut enode: B) -> Option<Id>
     where
         B: BorrowMut<L>,
@@ -1039,6 +1159,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             nodes: vec![enode.clone()],
             data: N::make(self, &original),
             parents: Default::default(),
+            touched: self.generation,
         };
 
         debug_assert_eq!(Id::from(self.nodes.len()), id);
@@ -1160,6 +1281,16 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             std::mem::swap(&mut id1, &mut id2);
         }
 
+        #[cfg(feature = "reports")]
+        if let Some(event_log) = &self.event_log {
+            event_log.log(&Event::Union {
+                from: enode_id1,
+                to: enode_id2,
+                root: id1,
+                justification: &rule,
+            });
+        }
+
         if let Some(explain) = &mut self.explain {
             explain.union(enode_id1, enode_id2, rule.unwrap());
         }
@@ -1181,6 +1312,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             self.analysis_pending.extend(class2.parents.iter().copied());
         }
 
+        class1.touched = self.generation;
         concat_vecs(&mut class1.nodes, class2.nodes);
         concat_vecs(&mut class1.parents, class2.parents);
 
@@ -1197,6 +1329,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         let id = self.find_mut(id);
         let class = self.classes.get_mut(&id).unwrap();
         class.data = new_data;
+        class.touched = self.generation;
         self.analysis_pending.extend(class.parents.iter().copied());
         N::modify(self, id)
     }
@@ -1350,6 +1483,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
                 let did_merge = self.analysis.merge(&mut class.data, node_data);
                 if did_merge.0 {
+                    class.touched = self.generation;
                     self.analysis_pending.extend(class.parents.iter().copied());
                     N::modify(self, class_id)
                 }
@@ -1428,9 +1562,76 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
         debug_assert!(self.check_memo());
         self.clean = true;
+        self.generation += 1;
         n_unions
     }
 
+    /// Returns the number of times this egraph has been rebuilt with
+    /// [`rebuild`](EGraph::rebuild()).
+    ///
+    /// Every eclass remembers the generation in which it was last
+    /// _touched_: created, merged into another eclass, or had its analysis
+    /// data changed by the egraph.
+    /// Remember the generation before searching, and you can later ask
+    /// which eclasses were touched since then with
+    /// [`touched_since`](EGraph::touched_since()), or search for only the
+    /// matches that involve them with
+    /// [`Searcher::search_touched`].
+    ///
+    /// Modifying [`EClass::data`] directly does not count as touching.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// egraph.rebuild();
+    ///
+    /// let generation = egraph.generation();
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// egraph.rebuild();
+    /// let touched: Vec<Id> = egraph.touched_since(generation).map(|c| c.id).collect();
+    /// assert_eq!(touched, vec![b]);
+    /// ```
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Iterates over the eclasses touched in or after `generation`,
+    /// see [`generation`](EGraph::generation()).
+    pub fn touched_since(&self, generation: usize) -> impl Iterator<Item = &EClass<L, N::Data>> {
+        self.classes()
+            .filter(move |class| class.touched >= generation)
+    }
+
+    /// The eclasses that are at most `depth` parent steps above any of
+    /// `classes`, including `classes` themselves, in breadth-first order.
+    pub(crate) fn ancestors_within(
+        &self,
+        classes: impl IntoIterator<Item = Id>,
+        depth: usize,
+    ) -> Vec<Id> {
+        let mut seen = HashSet::default();
+        let mut found: Vec<Id> = classes
+            .into_iter()
+            .map(|id| self.find(id))
+            .filter(|&id| seen.insert(id))
+            .collect();
+        let mut frontier = 0..found.len();
+        for _ in 0..depth {
+            let start = found.len();
+            for i in frontier {
+                for &parent in &self[found[i]].parents {
+                    let parent = self.find(parent);
+                    if seen.insert(parent) {
+                        found.push(parent);
+                    }
+                }
+            }
+            frontier = start..found.len();
+        }
+        found
+    }
+
     pub(crate) fn check_each_explain(&mut self, rules: &[&Rewrite<L, N>]) -> bool {
         if let Some(explain) = &mut self.explain {
             explain.with_nodes(&self.nodes).check_each_explain(rules)
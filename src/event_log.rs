@@ -0,0 +1,117 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::*;
+
+/// The writer given to
+/// [`Runner::with_event_log`](crate::Runner::with_event_log()).
+///
+/// The runner gives a handle to its [`EGraph`] while it runs an
+/// iteration, so the egraph can log unions without knowing about the
+/// runner.
+#[derive(Clone)]
+pub(crate) struct EventLog(Arc<Mutex<EventLogInner>>);
+
+struct EventLogInner {
+    writer: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+/// One line of the event log.
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    /// A match that is about to be applied.
+    Apply {
+        iteration: usize,
+        rule: Symbol,
+        class: Id,
+        subst: SubstJson<'a>,
+    },
+    /// Two e-classes were merged.
+    Union {
+        from: Id,
+        to: Id,
+        root: Id,
+        justification: &'a Option<Justification>,
+    },
+    /// The egraph was rebuilt after applying the rules.
+    Rebuild {
+        iteration: usize,
+        n_unions: usize,
+        time: f64,
+    },
+    /// An iteration finished.
+    Iteration {
+        iteration: usize,
+        egraph_nodes: usize,
+        egraph_classes: usize,
+        applied: &'a IndexMap<Symbol, usize>,
+        stop_reason: &'a Option<StopReason>,
+        phase: Option<Symbol>,
+    },
+}
+
+/// Serializes a [`Subst`] as a map from variable names to e-class ids,
+/// lists of them for sequence variables, or the e-node ids of the leaves
+/// leaf variables matched.
+pub(crate) struct SubstJson<'a>(pub &'a Subst);
+
+impl Serialize for SubstJson<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = self.0.vec.len() + self.0.seqs.len() + self.0.leaves.len();
+        let mut map = serializer.serialize_map(Some(len))?;
+        for (var, id) in &self.0.vec {
+            map.serialize_entry(&var.to_string(), id)?;
+        }
+        for (var, ids) in &self.0.seqs {
+            map.serialize_entry(&var.to_string(), ids)?;
+        }
+        for (var, node) in &self.0.leaves {
+            map.serialize_entry(&var.to_string(), node)?;
+        }
+        map.end()
+    }
+}
+
+impl EventLog {
+    pub(crate) fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(EventLogInner {
+            writer: Box::new(writer),
+            error: None,
+        })))
+    }
+
+    /// Writes `event` as one line of JSON.
+    /// After the first error, nothing more is written.
+    pub(crate) fn log(&self, event: &Event) {
+        let mut inner = self.0.lock().unwrap();
+        if inner.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut inner.writer, event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(inner.writer));
+        inner.error = result.err();
+    }
+
+    /// Flushes the writer, returning the first error since the last call,
+    /// if there was one.
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        let mut inner = self.0.lock().unwrap();
+        let result = inner.writer.flush();
+        match inner.error.take() {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+}
+
+impl Debug for EventLog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("<EventLog ..>")
+    }
+}
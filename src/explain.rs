@@ -812,20 +812,29 @@ impl<L: Language> FlatTerm<L> {
     fn from_pattern(
         pattern: &[ENodeOrVar<L>],
         location: usize,
-        bindings: &HashMap<Var, &FlatTerm<L>>,
+        bindings: &HashMap<Var, &[FlatTerm<L>]>,
     ) -> FlatTerm<L> {
         match &pattern[location] {
-            ENodeOrVar::Var(var) => (*bindings.get(var).unwrap()).clone(),
+            ENodeOrVar::Var(var) | ENodeOrVar::Guarded(var, _) | ENodeOrVar::Leaf(var, _) => {
+                bindings[var][0].clone()
+            }
+            ENodeOrVar::Seq(var) => panic!("Sequence var {} is not a child of an enode", var),
             ENodeOrVar::ENode(node) => {
-                let children = node.fold(vec![], |mut acc, child| {
-                    acc.push(FlatTerm::from_pattern(
-                        pattern,
-                        usize::from(child),
-                        bindings,
-                    ));
-                    acc
-                });
-                FlatTerm::new(node.clone(), children)
+                let mut children = vec![];
+                for &child in node.children() {
+                    match &pattern[usize::from(child)] {
+                        ENodeOrVar::Seq(var) => children.extend_from_slice(bindings[var]),
+                        _ => children.push(FlatTerm::from_pattern(
+                            pattern,
+                            usize::from(child),
+                            bindings,
+                        )),
+                    }
+                }
+                let node = node
+                    .with_children(vec![Id::from(0); children.len()])
+                    .unwrap();
+                FlatTerm::new(node, children)
             }
         }
     }
@@ -834,29 +843,55 @@ impl<L: Language> FlatTerm<L> {
         &'a self,
         pattern: &[ENodeOrVar<L>],
         location: usize,
-        bindings: &mut HashMap<Var, &'a FlatTerm<L>>,
+        bindings: &mut HashMap<Var, &'a [FlatTerm<L>]>,
     ) {
         match &pattern[location] {
-            ENodeOrVar::Var(var) => {
-                if let Some(existing) = bindings.get(var) {
-                    if existing != &self {
-                        panic!(
-                            "Invalid proof: binding for variable {:?} does not match between {:?} \n and \n {:?}",
-                            var, existing, self);
-                    }
-                } else {
-                    bindings.insert(*var, self);
-                }
+            ENodeOrVar::Var(var) | ENodeOrVar::Guarded(var, _) | ENodeOrVar::Leaf(var, _) => {
+                self.bind(*var, std::slice::from_ref(self), bindings)
             }
+            ENodeOrVar::Seq(var) => panic!("Sequence var {} is not a child of an enode", var),
             ENodeOrVar::ENode(node) => {
                 // The node must match the rewrite or the proof is invalid.
-                assert!(node.matches(&self.node));
-                let mut counter = 0;
-                node.for_each(|child| {
-                    self.children[counter].make_bindings(pattern, usize::from(child), bindings);
-                    counter += 1;
-                });
+                match crate::pattern::seq_position(pattern, node) {
+                    None => {
+                        assert!(node.matches(&self.node));
+                        for (child, term) in node.children().iter().zip(&self.children) {
+                            term.make_bindings(pattern, usize::from(*child), bindings);
+                        }
+                    }
+                    Some(seq) => {
+                        assert!(crate::pattern::matches_variadic(node, &self.node));
+                        let children = node.children();
+                        let end = self.children.len() - (children.len() - seq - 1);
+                        for (child, term) in children[..seq].iter().zip(&self.children) {
+                            term.make_bindings(pattern, usize::from(*child), bindings);
+                        }
+                        if let ENodeOrVar::Seq(var) = &pattern[usize::from(children[seq])] {
+                            self.bind(*var, &self.children[seq..end], bindings);
+                        }
+                        for (child, term) in children[seq + 1..].iter().zip(&self.children[end..]) {
+                            term.make_bindings(pattern, usize::from(*child), bindings);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn bind<'a>(
+        &self,
+        var: Var,
+        terms: &'a [FlatTerm<L>],
+        bindings: &mut HashMap<Var, &'a [FlatTerm<L>]>,
+    ) {
+        if let Some(existing) = bindings.get(&var) {
+            if *existing != terms {
+                panic!(
+                    "Invalid proof: binding for variable {:?} does not match between {:?} \n and \n {:?}",
+                    var, existing, terms);
             }
+        } else {
+            bindings.insert(var, terms);
         }
     }
 }
@@ -1,6 +1,8 @@
 use std::cmp::Ordering;
 use std::fmt::Debug;
 
+use num_traits::ToPrimitive;
+
 use crate::util::{hashmap_with_capacity, HashMap};
 use crate::{Analysis, EClass, EGraph, Id, Language, RecExpr};
 
@@ -293,6 +295,60 @@ where
     }
 }
 
+/// Removes the e-nodes of each e-class whose cost is more than `factor`
+/// times the best cost of that e-class, as in
+/// [`Runner::with_beam_pruning`](crate::Runner::with_beam_pruning()).
+/// Returns the cost function so it can be used again, and the number of
+/// e-nodes removed.
+pub(crate) fn prune_to_beam<CF, L, N>(
+    egraph: &mut EGraph<L, N>,
+    cost_function: CF,
+    factor: f64,
+) -> (CF, usize)
+where
+    CF: CostFunction<L>,
+    CF::Cost: ToPrimitive,
+    L: Language,
+    N: Analysis<L>,
+{
+    let mut extractor = Extractor::new(egraph, cost_function);
+    let eg = extractor.egraph;
+
+    // which nodes of each class to keep, only for classes that lose some
+    let mut keep: Vec<(Id, Vec<bool>)> = vec![];
+    for class in eg.classes() {
+        let bound = match extractor.costs.get(&class.id) {
+            Some((best, _)) => best.to_f64().map(|best| best * factor),
+            None => None,
+        };
+        let bound = match bound {
+            Some(bound) => bound,
+            None => continue,
+        };
+        let flags: Vec<bool> = class
+            .iter()
+            .map(|n| match extractor.node_total_cost(n) {
+                Some(cost) => cost.to_f64().map_or(true, |cost| cost <= bound),
+                None => true,
+            })
+            .collect();
+        if flags.contains(&false) {
+            keep.push((class.id, flags));
+        }
+    }
+
+    let cost_function = extractor.cost_function;
+    let mut pruned = 0;
+    for (id, flags) in keep {
+        let mut flags = flags.into_iter();
+        let class = &mut egraph[id];
+        let old_len = class.len();
+        class.nodes.retain(|_| flags.next().unwrap());
+        pruned += old_len - class.len();
+    }
+    (cost_function, pruned)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -45,6 +45,25 @@ pub trait Language: Debug + Clone + Eq + Ord + Hash {
     /// not the children `Id`s.
     fn matches(&self, other: &Self) -> bool;
 
+    /// Returns an e-node with the same operator as this one, but with the
+    /// given children, or `None` if the operator cannot have that many
+    /// children.
+    ///
+    /// [`Pattern`]s use this to match and build variadic operators with
+    /// sequence variables like `?xs...`.
+    /// The default implementation only allows as many children as this
+    /// e-node has; [`define_language!`] and [`SymbolLang`] allow any
+    /// number their children type can hold.
+    fn with_children(&self, children: Vec<Id>) -> Option<Self> {
+        if children.len() == self.len() {
+            let mut node = self.clone();
+            node.children_mut().copy_from_slice(&children);
+            Some(node)
+        } else {
+            None
+        }
+    }
+
     /// Returns the children of this e-node.
     fn children(&self) -> &[Id];
 
@@ -83,6 +102,30 @@ pub trait Language: Debug + Clone + Eq + Ord + Hash {
         self.all(|_| false)
     }
 
+    /// Returns the name of this enode's variant if it is a leaf that
+    /// carries data, like `Num` for `Num(2)`.
+    /// Leaf variables in patterns, written `?n!Num`, only match leaves
+    /// that return the name after the `!`.
+    ///
+    /// The default implementation returns `None`;
+    /// [`define_language!`] returns the variant name for each variant
+    /// written `Variant(Data)`, and [`SymbolLang`] returns `Symbol` for
+    /// every leaf, with its operator as the data.
+    fn leaf_variant(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the data of this enode if [`leaf_variant`](Language::leaf_variant)
+    /// returns `Some`, for [`Subst::leaf`] to downcast.
+    ///
+    /// The default implementation returns `None`.
+    fn leaf_data(&self) -> Option<&dyn std::any::Any>
+    where
+        Self: 'static,
+    {
+        None
+    }
+
     /// Runs a given function to replace the children.
     fn update_children<F: FnMut(Id) -> Id>(&mut self, mut f: F) {
         self.for_each_mut(|id| *id = f(*id))
@@ -953,6 +996,10 @@ impl Language for SymbolLang {
         self.op == other.op && self.len() == other.len()
     }
 
+    fn with_children(&self, children: Vec<Id>) -> Option<Self> {
+        Some(Self::new(self.op, children))
+    }
+
     fn children(&self) -> &[Id] {
         &self.children
     }
@@ -960,6 +1007,14 @@ impl Language for SymbolLang {
     fn children_mut(&mut self) -> &mut [Id] {
         &mut self.children
     }
+
+    fn leaf_variant(&self) -> Option<&'static str> {
+        self.is_leaf().then_some("Symbol")
+    }
+
+    fn leaf_data(&self) -> Option<&dyn std::any::Any> {
+        self.is_leaf().then_some(&self.op)
+    }
 }
 
 impl Display for SymbolLang {
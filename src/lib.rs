@@ -37,20 +37,29 @@ pub mod test;
 
 pub mod tutorials;
 
+mod binding;
 mod dot;
 mod eclass;
 mod egraph;
+#[cfg(feature = "reports")]
+mod event_log;
 mod explain;
 mod extract;
 mod language;
+mod lint;
 #[cfg(feature = "lp")]
 mod lp_extract;
 mod machine;
 mod multipattern;
 mod pattern;
+mod pattern_set;
+mod relational;
 mod rewrite;
+mod rule_graph;
 mod run;
+mod schedule;
 mod subst;
+mod unify;
 mod unionfind;
 mod util;
 
@@ -87,8 +96,12 @@ impl std::fmt::Display for Id {
 
 pub(crate) use {explain::Explain, unionfind::UnionFind};
 
+#[cfg(feature = "reports")]
+pub(crate) use event_log::{Event, EventLog, SubstJson};
+
 pub use {
-    dot::Dot,
+    binding::{BindingAnalysis, BindingLanguage, CaptureAvoid, FreeVars},
+    dot::{Dot, RuleGraphDot},
     eclass::EClass,
     egraph::{EGraph, LanguageMapper, SimpleLanguageMapper},
     explain::{
@@ -97,11 +110,20 @@ pub use {
     },
     extract::*,
     language::*,
+    lint::{RuleChecker, RuleLint},
+    machine::{Program, ProgramProfile},
     multipattern::*,
     pattern::{ENodeOrVar, Pattern, PatternAst, SearchMatches},
-    rewrite::{Applier, Condition, ConditionEqual, ConditionalApplier, Rewrite, Searcher},
+    pattern_set::PatternSet,
+    relational::RelationalPattern,
+    rewrite::{
+        AbsentSearcher, Applier, Condition, ConditionEqual, ConditionalApplier, Rewrite, Searcher,
+    },
+    rule_graph::RuleGraph,
     run::*,
+    schedule::{Schedule, ScheduleParseError},
     subst::{Subst, Var},
+    unify::{CriticalPair, Unifier},
     util::*,
 };
 
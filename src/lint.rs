@@ -0,0 +1,452 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::unify::subsumes_all;
+use crate::util::{HashMap, HashSet, IndexSet};
+use crate::*;
+
+/// A problem with a rule found by a [`RuleChecker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleLint {
+    /// The right-hand side of `rule` uses `var`, but the left-hand side
+    /// does not bind it, so [`Rewrite::new`] would fail.
+    UnboundVar {
+        /// The name of the rule.
+        rule: Symbol,
+        /// The unbound variable.
+        var: Var,
+    },
+    /// `rule` is the same as the earlier rule `original`, up to the
+    /// names of the variables.
+    Duplicate {
+        /// The name of the rule.
+        rule: Symbol,
+        /// The name of the earlier rule.
+        original: Symbol,
+    },
+    /// `rule` is an instance of the more general rule `general`, so every
+    /// rewrite it does, `general` does too.
+    Instance {
+        /// The name of the rule.
+        rule: Symbol,
+        /// The name of the more general rule.
+        general: Symbol,
+    },
+    /// Every term `rule` rewrites is smaller than its result, so `rule`
+    /// can keep growing the e-graph.
+    Growing {
+        /// The name of the rule.
+        rule: Symbol,
+    },
+    /// The left-hand side of `rule` needs an operator with a number of
+    /// children that neither the starting expressions nor the right-hand
+    /// side of any rule that can match ever creates.
+    Unmatchable {
+        /// The name of the rule.
+        rule: Symbol,
+    },
+}
+
+impl RuleLint {
+    /// Returns the name of the rule this is about.
+    pub fn rule(&self) -> Symbol {
+        match self {
+            RuleLint::UnboundVar { rule, .. }
+            | RuleLint::Duplicate { rule, .. }
+            | RuleLint::Instance { rule, .. }
+            | RuleLint::Growing { rule }
+            | RuleLint::Unmatchable { rule } => *rule,
+        }
+    }
+}
+
+impl Display for RuleLint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleLint::UnboundVar { rule, var } => write!(
+                f,
+                "rule `{}` uses {} on its right-hand side, but its left-hand side does not bind it",
+                rule, var
+            ),
+            RuleLint::Duplicate { rule, original } => {
+                write!(f, "rule `{}` duplicates rule `{}`", rule, original)
+            }
+            RuleLint::Instance { rule, general } => {
+                write!(f, "rule `{}` is an instance of rule `{}`", rule, general)
+            }
+            RuleLint::Growing { rule } => write!(f, "rule `{}` only grows terms", rule),
+            RuleLint::Unmatchable { rule } => write!(f, "rule `{}` can never match", rule),
+        }
+    }
+}
+
+/** Checks a set of rules for mistakes, reporting them as [`RuleLint`]s.
+
+Add rules with [`with_rule`](RuleChecker::with_rule()) or
+[`with_rewrite`](RuleChecker::with_rewrite()), and then
+[`check`](RuleChecker::check()) them.
+Only rules whose sides are both patterns are checked, and conditions
+are ignored, so a rule reported as an instance of a conditional rule
+may still be needed.
+
+To find rules that can never match, also add the expressions the
+e-graph starts from with [`with_expr`](RuleChecker::with_expr()).
+An operator with a given number of children can then only appear if
+one of those expressions or the right-hand side of a rule that can match
+has it.
+Appliers that are not patterns and [`Analysis::modify`] can also add
+e-nodes, so those rules may be reported by mistake.
+
+```
+use egg::*;
+
+let pat = |s: &str| -> Pattern<SymbolLang> { s.parse().unwrap() };
+let lints = RuleChecker::default()
+    .with_rule("comm", pat("(+ ?a ?b)"), pat("(+ ?b ?a)"))
+    .with_rule("comm2", pat("(+ ?x ?y)"), pat("(+ ?y ?x)"))
+    .with_rule("comm-zero", pat("(+ 0 ?b)"), pat("(+ ?b 0)"))
+    .with_rule("zero", pat("(+ ?a 0)"), pat("?b"))
+    .with_rule("add-zero", pat("?a"), pat("(+ ?a 0)"))
+    .with_rule("mul", pat("(* ?a ?b)"), pat("(* ?b ?a)"))
+    .with_expr(&"(+ x 1)".parse().unwrap())
+    .check();
+
+let lints: Vec<String> = lints.iter().map(|lint| lint.to_string()).collect();
+assert_eq!(lints, vec![
+    "rule `comm2` duplicates rule `comm`",
+    "rule `comm-zero` is an instance of rule `comm`",
+    "rule `zero` uses ?b on its right-hand side, but its left-hand side does not bind it",
+    "rule `add-zero` only grows terms",
+    "rule `mul` can never match",
+]);
+```
+**/
+#[derive(Debug, Clone)]
+pub struct RuleChecker<L> {
+    rules: Vec<(Symbol, PatternAst<L>, PatternAst<L>)>,
+    exprs: Vec<RecExpr<L>>,
+}
+
+impl<L> Default for RuleChecker<L> {
+    fn default() -> Self {
+        RuleChecker {
+            rules: vec![],
+            exprs: vec![],
+        }
+    }
+}
+
+impl<L: Language> RuleChecker<L> {
+    /// Adds a rule that rewrites `lhs` to `rhs`.
+    ///
+    /// Unlike [`Rewrite::new`], this accepts rules whose right-hand side
+    /// uses variables the left-hand side does not bind, so
+    /// [`check`](RuleChecker::check()) can report them.
+    pub fn with_rule(mut self, name: impl Into<Symbol>, lhs: Pattern<L>, rhs: Pattern<L>) -> Self {
+        self.rules.push((name.into(), lhs.ast, rhs.ast));
+        self
+    }
+
+    /// Adds a [`Rewrite`], if both of its sides are patterns.
+    pub fn with_rewrite<N: Analysis<L>>(mut self, rewrite: &Rewrite<L, N>) -> Self {
+        let lhs = rewrite.searcher.get_pattern_ast();
+        let rhs = rewrite.applier.get_pattern_ast();
+        if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+            self.rules.push((rewrite.name, lhs.clone(), rhs.clone()));
+        }
+        self
+    }
+
+    /// Adds an expression the e-graph starts from, enabling the check for
+    /// rules that can never match.
+    pub fn with_expr(mut self, expr: &RecExpr<L>) -> Self {
+        self.exprs.push(expr.clone());
+        self
+    }
+
+    /// Checks the rules, returning the lints for each rule in the order
+    /// the rules were added.
+    ///
+    /// Rules with sequence variables are not checked for duplicates or
+    /// instances.
+    pub fn check(&self) -> Vec<RuleLint> {
+        let unmatchable = self.unmatchable();
+        let mut lints = vec![];
+        for (i, (rule, lhs, rhs)) in self.rules.iter().enumerate() {
+            let rule = *rule;
+            let bound = vars(lhs);
+            let unbound: Vec<Var> = vars(rhs)
+                .into_iter()
+                .filter(|v| !bound.contains(v))
+                .collect();
+            for &var in &unbound {
+                lints.push(RuleLint::UnboundVar { rule, var });
+            }
+
+            lints.extend(self.redundancy(i));
+            if unbound.is_empty() && grows(lhs, rhs) {
+                lints.push(RuleLint::Growing { rule });
+            }
+            if unmatchable.contains(&i) {
+                lints.push(RuleLint::Unmatchable { rule });
+            }
+        }
+        lints
+    }
+
+    /// Finds an earlier rule that rule `i` duplicates, or else a more
+    /// general rule.
+    fn redundancy(&self, i: usize) -> Option<RuleLint> {
+        let (rule, lhs, rhs) = &self.rules[i];
+        let mut general = None;
+        for (j, (other, l, r)) in self.rules.iter().enumerate() {
+            // subsumption does not support sequence variables
+            if j == i || [lhs, rhs, l, r].iter().any(|pat| has_seqs(pat)) {
+                continue;
+            }
+            if subsumes_all(&[l, r], &[lhs, rhs]) {
+                if !subsumes_all(&[lhs, rhs], &[l, r]) {
+                    general = general.or(Some(*other));
+                } else if j < i {
+                    return Some(RuleLint::Duplicate {
+                        rule: *rule,
+                        original: *other,
+                    });
+                }
+            }
+        }
+        general.map(|general| RuleLint::Instance {
+            rule: *rule,
+            general,
+        })
+    }
+
+    /// Returns the indices of the rules that can never match, by finding
+    /// the operators that can appear, starting from the expressions.
+    fn unmatchable(&self) -> IndexSet<usize> {
+        if self.exprs.is_empty() {
+            return IndexSet::default();
+        }
+
+        let mut ops = Operators::default();
+        for expr in &self.exprs {
+            expr.as_ref().iter().for_each(|node| ops.add(node, false));
+        }
+        let mut unmatchable: IndexSet<usize> = (0..self.rules.len()).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, (_, lhs, rhs)) in self.rules.iter().enumerate() {
+                if unmatchable.contains(&i) && ops.can_match(lhs) {
+                    unmatchable.shift_remove(&i);
+                    ops.add_pattern(rhs);
+                    changed = true;
+                }
+            }
+        }
+        unmatchable
+    }
+}
+
+/// The operators that can appear in the e-graph, with their number of
+/// children.
+struct Operators<L: Language> {
+    fixed: HashSet<(L::Discriminant, usize)>,
+    /// Operators built from a sequence variable, with any number of
+    /// children.
+    variadic: HashSet<L::Discriminant>,
+}
+
+impl<L: Language> Default for Operators<L> {
+    fn default() -> Self {
+        Operators {
+            fixed: Default::default(),
+            variadic: Default::default(),
+        }
+    }
+}
+
+impl<L: Language> Operators<L> {
+    fn add(&mut self, node: &L, variadic: bool) {
+        if variadic {
+            self.variadic.insert(node.discriminant());
+        } else {
+            self.fixed.insert((node.discriminant(), node.len()));
+        }
+    }
+
+    fn add_pattern(&mut self, pat: &PatternAst<L>) {
+        for node in pat.as_ref() {
+            if let ENodeOrVar::ENode(n) = node {
+                self.add(n, has_seq(pat, n));
+            }
+        }
+    }
+
+    fn can_match(&self, pat: &PatternAst<L>) -> bool {
+        pat.as_ref().iter().all(|node| match node {
+            ENodeOrVar::ENode(n) => {
+                let op = n.discriminant();
+                if self.variadic.contains(&op) {
+                    true
+                } else if has_seq(pat, n) {
+                    let min = n.len() - 1;
+                    self.fixed.iter().any(|(o, len)| *o == op && *len >= min)
+                } else {
+                    self.fixed.contains(&(op, n.len()))
+                }
+            }
+            _ => true,
+        })
+    }
+}
+
+fn has_seqs<L: Language>(pat: &PatternAst<L>) -> bool {
+    pat.as_ref()
+        .iter()
+        .any(|node| matches!(node, ENodeOrVar::Seq(_)))
+}
+
+fn has_seq<L: Language>(pat: &PatternAst<L>, node: &L) -> bool {
+    node.children()
+        .iter()
+        .any(|&c| matches!(pat[c], ENodeOrVar::Seq(_)))
+}
+
+fn vars<L: Language>(pat: &PatternAst<L>) -> Vec<Var> {
+    let mut vars = vec![];
+    for node in pat.as_ref() {
+        if let ENodeOrVar::Var(v)
+        | ENodeOrVar::Guarded(v, _)
+        | ENodeOrVar::Seq(v)
+        | ENodeOrVar::Leaf(v, _) = node
+        {
+            if !vars.contains(v) {
+                vars.push(*v);
+            }
+        }
+    }
+    vars
+}
+
+/// Counts the e-nodes and the occurrences of each variable in the term
+/// at `id`, counting shared subterms once for each time they occur.
+fn count<L: Language>(
+    pat: &PatternAst<L>,
+    id: Id,
+    enodes: &mut usize,
+    vars: &mut HashMap<Var, usize>,
+) {
+    match &pat[id] {
+        ENodeOrVar::ENode(n) => {
+            *enodes += 1;
+            for &c in n.children() {
+                count(pat, c, enodes, vars);
+            }
+        }
+        ENodeOrVar::Var(v)
+        | ENodeOrVar::Guarded(v, _)
+        | ENodeOrVar::Seq(v)
+        | ENodeOrVar::Leaf(v, _) => *vars.entry(*v).or_default() += 1,
+    }
+}
+
+/// Returns `true` if every instance of `rhs` is bigger than the matching
+/// instance of `lhs`.
+///
+/// Each variable stands for a term of at least one e-node, except
+/// sequence variables, which may stand for none.
+fn grows<L: Language>(lhs: &PatternAst<L>, rhs: &PatternAst<L>) -> bool {
+    let (mut lhs_enodes, mut lhs_vars) = (0, HashMap::default());
+    let (mut rhs_enodes, mut rhs_vars) = (0, HashMap::default());
+    count(lhs, lhs.root(), &mut lhs_enodes, &mut lhs_vars);
+    count(rhs, rhs.root(), &mut rhs_enodes, &mut rhs_vars);
+    let seqs = vars(lhs)
+        .into_iter()
+        .filter(|v| lhs.as_ref().contains(&ENodeOrVar::Seq(*v)))
+        .collect::<Vec<Var>>();
+
+    let mut growth = rhs_enodes as isize - lhs_enodes as isize;
+    for (v, &n) in &lhs_vars {
+        let m = rhs_vars.get(v).copied().unwrap_or(0);
+        if m < n {
+            return false;
+        }
+        if !seqs.contains(v) {
+            growth += (m - n) as isize;
+        }
+    }
+    growth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rewrite as rw, *};
+
+    #[test]
+    fn check_rules() {
+        define_language! {
+            enum Lists {
+                Num(i32),
+                "list" = List(Vec<Id>),
+                "len" = Len(Id),
+                "+" = Add([Id; 2]),
+            }
+        }
+
+        let pat = |s: &str| -> Pattern<Lists> { s.parse().unwrap() };
+        let rules: Vec<Rewrite<Lists, ()>> = vec![
+            rw!("len-nil"; "(len (list))" => "0"),
+            rw!("len-cons"; "(len (list ?x ?xs...))" => "(+ 1 (len (list ?xs...)))"),
+            rw!("add-comm"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+            rw!("add-one"; "(+ 1 ?b)" => "(+ ?b 1)"),
+        ];
+        let checker = rules
+            .iter()
+            .fold(RuleChecker::default(), |checker, rw| {
+                checker.with_rewrite(rw)
+            })
+            .with_rule("double", pat("(+ ?a ?a)"), pat("(+ (+ ?a 0) ?a)"))
+            .with_rule("seq-grow", pat("(list ?xs...)"), pat("(list 0 ?xs...)"))
+            .with_rule("leaf", pat("?n!Num"), pat("(+ ?n!Num ?m)"));
+        let lints = checker.clone().check();
+        let expected = vec![
+            RuleLint::Instance {
+                rule: "add-one".into(),
+                general: "add-comm".into(),
+            },
+            RuleLint::Growing {
+                rule: "double".into(),
+            },
+            RuleLint::Growing {
+                rule: "seq-grow".into(),
+            },
+            RuleLint::UnboundVar {
+                rule: "leaf".into(),
+                var: "?m".parse().unwrap(),
+            },
+        ];
+        assert_eq!(lints, expected);
+
+        // without a list to start from, no list rule can match
+        let lints = checker
+            .clone()
+            .with_expr(&"(+ 1 2)".parse().unwrap())
+            .check();
+        let unmatchable: Vec<&str> = lints
+            .iter()
+            .filter(|lint| matches!(lint, RuleLint::Unmatchable { .. }))
+            .map(|lint| lint.rule().as_str())
+            .collect();
+        assert_eq!(unmatchable, vec!["len-nil", "len-cons", "seq-grow"]);
+
+        let lints = checker
+            .with_expr(&"(len (list 3))".parse().unwrap())
+            .check();
+        let unmatchable: Vec<&str> = lints
+            .iter()
+            .filter(|lint| matches!(lint, RuleLint::Unmatchable { .. }))
+            .map(|lint| lint.rule().as_str())
+            .collect();
+        assert!(unmatchable.is_empty());
+    }
+}
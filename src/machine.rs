@@ -1,4 +1,5 @@
 use crate::*;
+use std::fmt::{self, Display, Formatter};
 use std::result;
 
 type Result = result::Result<(), ()>;
@@ -6,25 +7,129 @@ type Result = result::Result<(), ()>;
 #[derive(Default)]
 struct Machine {
     reg: Vec<Id>,
+    /// The sequence registers, each holding a list of e-classes.
+    seqs: Vec<Vec<Id>>,
     // a buffer to re-use for lookups
     lookup: Vec<Id>,
+    profile: Option<ProgramProfile>,
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Reg(u32);
 
+/** The e-matching program a [`Pattern`] or [`MultiPattern`] is compiled
+to.
+
+A program is a list of instructions run by a backtracking virtual
+machine with a list of registers, each holding an e-class.
+Register `r0` holds the e-class being searched, and each pattern
+variable ends up in one of the registers.
+The instructions are:
+
+- `bind ri (op rj rk ..)`: for each e-node `op` in the e-class in `ri`,
+  put its children in `rj`, `rk`, .. and run the rest of the program.
+- `compare ri rj`: continue only if `ri` and `rj` hold the same e-class.
+- `lookup ri term`: continue only if `term`, whose variables are
+  registers, is in the e-graph and is in the e-class in `ri`.
+- `scan ri`: for each e-class in the e-graph, put it in `ri` and run
+  the rest of the program. This starts each pattern of a
+  [`MultiPattern`] after the first.
+- `guard ri name`: continue only if the e-class in `ri` passes the
+  guard `name` (see [`EGraph::with_guard`]).
+- `bind ri (op rj sk... rl)`: like `bind`, for an `op` with a sequence
+  variable among its children, matching e-nodes with any number of
+  children in its place and putting them in the sequence register `sk`.
+- `compare si sj`: continue only if the sequence registers `si` and `sj`
+  hold the same e-classes.
+- `leaf ri V rj`: for each childless e-node of the variant `V` in the
+  e-class in `ri`, put its e-node [`Id`] in `rj` and run the rest of the
+  program.
+  This binds the leaf variables (like `?n!Num`) at the end of the program.
+
+The [`Display`] implementation prints the program one instruction per
+line, after the register of each variable.
+When a pattern is slow, [`profile`](Program::profile()) shows where the
+search spends its time.
+
+```
+# use egg::*;
+let pattern: Pattern<SymbolLang> = "(+ ?a (* ?a ?b))".parse().unwrap();
+assert_eq!(
+    pattern.program().to_string(),
+    "?a = r1, ?b = r4
+0: bind r0 (+ r1 r2)
+1: bind r2 (* r3 r4)
+2: compare r3 r1
+"
+);
+```
+*/
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Program<L> {
     instructions: Vec<Instruction<L>>,
     subst: Subst,
+    /// The number of registers the program uses.
+    n_regs: usize,
+}
+
+/// Many [`Program`]s merged into a trie, so the instructions they start
+/// with in common only run once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProgramTrie<L> {
+    /// The root is node 0, and is the only node without an instruction.
+    nodes: Vec<TrieNode<L>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrieNode<L> {
+    instruction: Option<Instruction<L>>,
+    children: Vec<usize>,
+    /// The programs that end here, with their index, the registers their
+    /// variables are in, and how many registers they use.
+    programs: Vec<(usize, Subst, usize)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Instruction<L> {
-    Bind { node: L, i: Reg, out: Reg },
-    Compare { i: Reg, j: Reg },
-    Lookup { term: Vec<ENodeOrReg<L>>, i: Reg },
-    Scan { out: Reg },
+    Bind {
+        node: L,
+        i: Reg,
+        out: Reg,
+    },
+    Compare {
+        i: Reg,
+        j: Reg,
+    },
+    Lookup {
+        term: Vec<ENodeOrReg<L>>,
+        i: Reg,
+    },
+    Scan {
+        out: Reg,
+    },
+    Guard {
+        i: Reg,
+        guard: Symbol,
+    },
+    /// Like `Bind`, but the child at `at` is a sequence variable, which
+    /// binds sequence register `seq`, and `node` has a placeholder child
+    /// there.
+    BindSeq {
+        node: L,
+        i: Reg,
+        out: Reg,
+        at: usize,
+        seq: usize,
+    },
+    CompareSeq {
+        i: usize,
+        j: usize,
+    },
+    Leaf {
+        i: Reg,
+        variant: Symbol,
+        out: Reg,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,6 +138,28 @@ enum ENodeOrReg<L> {
     Reg(Reg),
 }
 
+/// How often each instruction of a [`Program`] ran, made by
+/// [`Program::profile`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProgramProfile {
+    /// For each instruction, the number of times it ran.
+    pub executed: Vec<usize>,
+    /// For each instruction, the number of times it ran and the rest of
+    /// the program did not run, because a `bind` found no e-nodes or a
+    /// `compare` or `lookup` failed.
+    pub failed: Vec<usize>,
+    /// The number of matches found.
+    pub matches: usize,
+}
+
+/// The leaves of the given variant in an e-class, for a `leaf` instruction.
+fn leaves_of<L: Language, D>(eclass: &EClass<L, D>, variant: Symbol) -> impl Iterator<Item = &L> {
+    eclass
+        .leaves()
+        .filter(move |leaf| leaf.leaf_variant() == Some(variant.as_str()))
+}
+
 impl Machine {
     #[inline(always)]
     fn reg(&self, reg: Reg) -> Id {
@@ -52,15 +179,66 @@ impl Machine {
     {
         let mut instructions = instructions.iter();
         while let Some(instruction) = instructions.next() {
+            // the index of this instruction, if we are profiling
+            let index = self.profile.as_mut().map(|profile| {
+                let index = profile.executed.len() - instructions.len() - 1;
+                profile.executed[index] += 1;
+                index
+            });
             match instruction {
                 Instruction::Bind { i, out, node } => {
                     let remaining_instructions = instructions.as_slice();
                     let eclass = &egraph[self.reg(*i)];
-                    return eclass.for_each_matching_node(node, |matched| {
+                    let mut found = false;
+                    let result = eclass.for_each_matching_node(node, |matched| {
+                        found = true;
                         self.reg.truncate(out.0 as usize);
                         matched.for_each(|id| self.reg.push(id));
                         self.run(egraph, remaining_instructions, subst, yield_fn)
                     });
+                    if !found {
+                        self.fail(index);
+                    }
+                    return result;
+                }
+                Instruction::BindSeq {
+                    node,
+                    i,
+                    out,
+                    at,
+                    seq,
+                } => {
+                    let remaining_instructions = instructions.as_slice();
+                    let eclass = &egraph[self.reg(*i)];
+                    let mut found = false;
+                    let result = eclass
+                        .nodes
+                        .iter()
+                        .filter(|n| pattern::matches_variadic(node, n))
+                        .try_for_each(|matched| {
+                            found = true;
+                            self.bind_seq(node, matched, *out, *at, *seq);
+                            self.run(egraph, remaining_instructions, subst, yield_fn)
+                        });
+                    if !found {
+                        self.fail(index);
+                    }
+                    return result;
+                }
+                Instruction::Leaf { i, variant, out } => {
+                    let remaining_instructions = instructions.as_slice();
+                    let eclass = &egraph[self.reg(*i)];
+                    let mut found = false;
+                    let result = leaves_of(eclass, *variant).try_for_each(|leaf| {
+                        found = true;
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.push(egraph.leaf_id(leaf));
+                        self.run(egraph, remaining_instructions, subst, yield_fn)
+                    });
+                    if !found {
+                        self.fail(index);
+                    }
+                    return result;
                 }
                 Instruction::Scan { out } => {
                     let remaining_instructions = instructions.as_slice();
@@ -72,29 +250,26 @@ impl Machine {
                     return Ok(());
                 }
                 Instruction::Compare { i, j } => {
-                    if egraph.find(self.reg(*i)) != egraph.find(self.reg(*j)) {
+                    if !self.compare(egraph, *i, *j) {
+                        self.fail(index);
                         return Ok(());
                     }
                 }
                 Instruction::Lookup { term, i } => {
-                    self.lookup.clear();
-                    for node in term {
-                        match node {
-                            ENodeOrReg::ENode(node) => {
-                                let look = |i| self.lookup[usize::from(i)];
-                                match egraph.lookup(node.clone().map_children(look)) {
-                                    Some(id) => self.lookup.push(id),
-                                    None => return Ok(()),
-                                }
-                            }
-                            ENodeOrReg::Reg(r) => {
-                                self.lookup.push(egraph.find(self.reg(*r)));
-                            }
-                        }
+                    if !self.lookup(egraph, term, *i) {
+                        self.fail(index);
+                        return Ok(());
                     }
-
-                    let id = egraph.find(self.reg(*i));
-                    if self.lookup.last().copied() != Some(id) {
+                }
+                Instruction::Guard { i, guard } => {
+                    if !egraph.check_guard(*guard, self.reg(*i)) {
+                        self.fail(index);
+                        return Ok(());
+                    }
+                }
+                Instruction::CompareSeq { i, j } => {
+                    if !self.compare_seq(egraph, *i, *j) {
+                        self.fail(index);
                         return Ok(());
                     }
                 }
@@ -103,6 +278,197 @@ impl Machine {
 
         yield_fn(self, subst)
     }
+
+    /// Puts the children of `matched` in the registers from `out`, except
+    /// for the ones in place of the sequence variable at `at` in `node`,
+    /// which go in sequence register `seq`.
+    fn bind_seq<L: Language>(&mut self, node: &L, matched: &L, out: Reg, at: usize, seq: usize) {
+        let children = matched.children();
+        let end = children.len() + at + 1 - node.len();
+        self.reg.truncate(out.0 as usize);
+        self.reg.extend_from_slice(&children[..at]);
+        self.reg.extend_from_slice(&children[end..]);
+        self.seqs.truncate(seq);
+        self.seqs.push(children[at..end].to_vec());
+    }
+
+    fn fail(&mut self, index: Option<usize>) {
+        if let (Some(profile), Some(index)) = (&mut self.profile, index) {
+            profile.failed[index] += 1;
+        }
+    }
+
+    /// The match found by the program with variables in the registers
+    /// given by `subst`, or `None` if it should be skipped.
+    fn instantiate<L, N>(&self, egraph: &EGraph<L, N>, subst: &Subst) -> Option<Subst>
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        if !egraph.analysis.allow_ematching_cycles() {
+            if let Some((first, rest)) = self.reg.split_first() {
+                if rest.contains(first) {
+                    return None;
+                }
+            }
+        }
+
+        let subst_vec = subst
+            .vec
+            .iter()
+            // HACK we are reusing Ids here, this is bad
+            .map(|(v, reg_id)| (*v, self.reg(Reg(usize::from(*reg_id) as u32))))
+            .collect();
+        let seqs = subst
+            .seqs
+            .iter()
+            // the same HACK, with the sequence register in the only Id
+            .map(|(v, seq)| (*v, self.seqs[usize::from(seq[0])].clone()))
+            .collect();
+        let leaves = subst
+            .leaves
+            .iter()
+            .map(|(v, reg_id)| (*v, self.reg(Reg(usize::from(*reg_id) as u32))))
+            .collect();
+        Some(Subst {
+            vec: subst_vec,
+            seqs,
+            leaves,
+        })
+    }
+
+    fn compare<L, N>(&self, egraph: &EGraph<L, N>, i: Reg, j: Reg) -> bool
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        egraph.find(self.reg(i)) == egraph.find(self.reg(j))
+    }
+
+    fn compare_seq<L, N>(&self, egraph: &EGraph<L, N>, i: usize, j: usize) -> bool
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        let (a, b) = (&self.seqs[i], &self.seqs[j]);
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(&a, &b)| egraph.find(a) == egraph.find(b))
+    }
+
+    fn lookup<L, N>(&mut self, egraph: &EGraph<L, N>, term: &[ENodeOrReg<L>], i: Reg) -> bool
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        self.lookup.clear();
+        for node in term {
+            match node {
+                ENodeOrReg::ENode(node) => {
+                    let look = |i| self.lookup[usize::from(i)];
+                    match egraph.lookup(node.clone().map_children(look)) {
+                        Some(id) => self.lookup.push(id),
+                        None => return false,
+                    }
+                }
+                ENodeOrReg::Reg(r) => {
+                    self.lookup.push(egraph.find(self.reg(*r)));
+                }
+            }
+        }
+
+        let id = egraph.find(self.reg(i));
+        self.lookup.last().copied() == Some(id)
+    }
+
+    /// Like [`run`](Machine::run()), but runs the program below trie
+    /// node `node`, calling `yield_fn` with the index of each program
+    /// that matched.
+    fn run_trie<L, N>(
+        &mut self,
+        egraph: &EGraph<L, N>,
+        trie: &ProgramTrie<L>,
+        node: usize,
+        yield_fn: &mut impl FnMut(&Self, usize, &Subst) -> Result,
+    ) -> Result
+    where
+        L: Language,
+        N: Analysis<L>,
+    {
+        let trie_node = &trie.nodes[node];
+        for (index, subst, n_regs) in &trie_node.programs {
+            // registers past `n_regs` may be left over from other
+            // programs, and the rest of the trie never reads them
+            self.reg.truncate(*n_regs);
+            yield_fn(self, *index, subst)?;
+        }
+        for &child in &trie_node.children {
+            match trie.nodes[child].instruction.as_ref().unwrap() {
+                Instruction::Bind { i, out, node } => {
+                    let eclass = &egraph[self.reg(*i)];
+                    eclass.for_each_matching_node(node, |matched| {
+                        self.reg.truncate(out.0 as usize);
+                        matched.for_each(|id| self.reg.push(id));
+                        self.run_trie(egraph, trie, child, yield_fn)
+                    })?;
+                }
+                Instruction::BindSeq {
+                    node,
+                    i,
+                    out,
+                    at,
+                    seq,
+                } => {
+                    let eclass = &egraph[self.reg(*i)];
+                    eclass
+                        .nodes
+                        .iter()
+                        .filter(|n| pattern::matches_variadic(node, n))
+                        .try_for_each(|matched| {
+                            self.bind_seq(node, matched, *out, *at, *seq);
+                            self.run_trie(egraph, trie, child, yield_fn)
+                        })?;
+                }
+                Instruction::Leaf { i, variant, out } => {
+                    let eclass = &egraph[self.reg(*i)];
+                    leaves_of(eclass, *variant).try_for_each(|leaf| {
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.push(egraph.leaf_id(leaf));
+                        self.run_trie(egraph, trie, child, yield_fn)
+                    })?;
+                }
+                Instruction::Scan { out } => {
+                    for class in egraph.classes() {
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.push(class.id);
+                        self.run_trie(egraph, trie, child, yield_fn)?
+                    }
+                }
+                Instruction::Compare { i, j } => {
+                    if self.compare(egraph, *i, *j) {
+                        self.run_trie(egraph, trie, child, yield_fn)?
+                    }
+                }
+                Instruction::Lookup { term, i } => {
+                    if self.lookup(egraph, term, *i) {
+                        self.run_trie(egraph, trie, child, yield_fn)?
+                    }
+                }
+                Instruction::Guard { i, guard } => {
+                    if egraph.check_guard(*guard, self.reg(*i)) {
+                        self.run_trie(egraph, trie, child, yield_fn)?
+                    }
+                }
+                Instruction::CompareSeq { i, j } => {
+                    if self.compare_seq(egraph, *i, *j) {
+                        self.run_trie(egraph, trie, child, yield_fn)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 struct Compiler<L> {
@@ -112,6 +478,15 @@ struct Compiler<L> {
     todo_nodes: HashMap<(Id, Reg), L>,
     instructions: Vec<Instruction<L>>,
     next_reg: Reg,
+    /// The guards on variables that are not bound yet.
+    guards: IndexMap<Var, Vec<Symbol>>,
+    /// The sequence register of each sequence variable.
+    seqs: IndexMap<Var, usize>,
+    next_seq: usize,
+    /// The register holding the enode of each leaf variable.
+    leaves: IndexMap<Var, Reg>,
+    /// The leaf variables to bind at the end of the current pattern.
+    pending_leaves: Vec<(Var, Symbol)>,
 }
 
 impl<L: Language> Compiler<L> {
@@ -123,21 +498,45 @@ impl<L: Language> Compiler<L> {
             todo_nodes: Default::default(),
             instructions: Default::default(),
             next_reg: Reg(0),
+            guards: Default::default(),
+            seqs: Default::default(),
+            next_seq: 0,
+            leaves: Default::default(),
+            pending_leaves: Default::default(),
+        }
+    }
+
+    /// Puts `v` in `reg`, checking its guards.
+    fn bind_var(&mut self, v: Var, reg: Reg) {
+        self.v2r.insert(v, reg);
+        for guard in self.guards.swap_remove(&v).unwrap_or_default() {
+            self.instructions.push(Instruction::Guard { i: reg, guard });
+        }
+    }
+
+    /// Puts sequence variable `v` in sequence register `seq`.
+    fn bind_seq_var(&mut self, v: Var, seq: usize) {
+        if let Some(&j) = self.seqs.get(&v) {
+            self.instructions
+                .push(Instruction::CompareSeq { i: seq, j });
+        } else {
+            self.seqs.insert(v, seq);
         }
     }
 
     fn add_todo(&mut self, pattern: &PatternAst<L>, id: Id, reg: Reg) {
         match &pattern[id] {
-            ENodeOrVar::Var(v) => {
+            ENodeOrVar::Var(v) | ENodeOrVar::Guarded(v, _) | ENodeOrVar::Leaf(v, _) => {
                 if let Some(&j) = self.v2r.get(v) {
                     self.instructions.push(Instruction::Compare { i: reg, j })
                 } else {
-                    self.v2r.insert(*v, reg);
+                    self.bind_var(*v, reg);
                 }
             }
             ENodeOrVar::ENode(pat) => {
                 self.todo_nodes.insert((id, reg), pat.clone());
             }
+            ENodeOrVar::Seq(v) => panic!("Sequence var {} must be a child of an enode", v),
         }
     }
 
@@ -157,9 +556,32 @@ impl<L: Language> Compiler<L> {
                         size += self.subtree_size[usize::from(child)];
                     }
                 }
-                ENodeOrVar::Var(v) => {
+                // sequence variables are never in a register, so terms
+                // with them are never ground
+                ENodeOrVar::Var(v) | ENodeOrVar::Seq(v) => {
                     free.insert(*v);
                 }
+                ENodeOrVar::Leaf(v, variant) => {
+                    free.insert(*v);
+                    let pending = self.pending_leaves.iter().any(|(w, _)| w == v);
+                    if !self.leaves.contains_key(v) && !pending {
+                        self.pending_leaves.push((*v, *variant));
+                    }
+                }
+                ENodeOrVar::Guarded(v, guard) => {
+                    free.insert(*v);
+                    // a guard on a variable bound by an earlier pattern
+                    // can be checked right away
+                    if let Some(&i) = self.v2r.get(v) {
+                        let guard = *guard;
+                        self.instructions.push(Instruction::Guard { i, guard });
+                    } else {
+                        let guards = self.guards.entry(*v).or_default();
+                        if !guards.contains(guard) {
+                            guards.push(*guard);
+                        }
+                    }
+                }
             }
             self.free_vars.push(free);
             self.subtree_size.push(size);
@@ -222,7 +644,7 @@ impl<L: Language> Compiler<L> {
                 // patternbinder is new variable
                 next_out.0 += 1;
                 add_new_pattern(self);
-                self.v2r.insert(v, self.next_reg); //add to known variables.
+                self.bind_var(v, self.next_reg); //add to known variables.
             }
         } else {
             // No pattern binder
@@ -239,10 +661,37 @@ impl<L: Language> Compiler<L> {
                         .iter()
                         .map(|n| match n {
                             ENodeOrVar::ENode(n) => ENodeOrReg::ENode(n.clone()),
-                            ENodeOrVar::Var(v) => ENodeOrReg::Reg(self.v2r[v]),
+                            ENodeOrVar::Var(v)
+                            | ENodeOrVar::Guarded(v, _)
+                            | ENodeOrVar::Leaf(v, _) => ENodeOrReg::Reg(self.v2r[v]),
+                            ENodeOrVar::Seq(_) => {
+                                unreachable!("terms with sequence vars are not ground")
+                            }
                         })
                         .collect(),
                 });
+            } else if let Some(at) = pattern::seq_position(pattern.as_ref(), &node) {
+                let out = next_out;
+                next_out.0 += node.len() as u32 - 1;
+                let seq = self.next_seq;
+                self.next_seq += 1;
+
+                let op = node.clone().map_children(|_| Id::from(0));
+                self.instructions.push(Instruction::BindSeq {
+                    i: reg,
+                    node: op,
+                    out,
+                    at,
+                    seq,
+                });
+
+                let mut regs = (out.0..).map(Reg);
+                for (i, &child) in node.children().iter().enumerate() {
+                    match &pattern[child] {
+                        ENodeOrVar::Seq(v) if i == at => self.bind_seq_var(*v, seq),
+                        _ => self.add_todo(pattern, child, regs.next().unwrap()),
+                    }
+                }
             } else {
                 let out = next_out;
                 next_out.0 += node.len() as u32;
@@ -260,6 +709,19 @@ impl<L: Language> Compiler<L> {
                 }
             }
         }
+
+        // enumerate the leaves last, since each one repeats the rest of
+        // the program
+        for (v, variant) in std::mem::take(&mut self.pending_leaves) {
+            let i = self.v2r[&v];
+            self.instructions.push(Instruction::Leaf {
+                i,
+                variant,
+                out: next_out,
+            });
+            self.leaves.insert(v, next_out);
+            next_out.0 += 1;
+        }
         self.next_reg = next_out;
     }
 
@@ -268,9 +730,16 @@ impl<L: Language> Compiler<L> {
         for (v, r) in self.v2r {
             subst.insert(v, Id::from(r.0 as usize));
         }
+        for (v, seq) in self.seqs {
+            subst.insert_seq(v, vec![Id::from(seq)]);
+        }
+        for (v, r) in self.leaves {
+            subst.insert_leaf(v, Id::from(r.0 as usize));
+        }
         Program {
             instructions: self.instructions,
             subst,
+            n_regs: self.next_reg.0 as usize,
         }
     }
 }
@@ -292,8 +761,67 @@ impl<L: Language> Program<L> {
         compiler.extract()
     }
 
-    pub fn run_with_limit<A>(
+    /// Runs the program on `eclass`, returning at most `limit` matches.
+    pub fn run_with_limit<A>(&self, egraph: &EGraph<L, A>, eclass: Id, limit: usize) -> Vec<Subst>
+    where
+        A: Analysis<L>,
+    {
+        self.run_machine(&mut Machine::default(), egraph, eclass, limit)
+    }
+
+    /// Runs the program on each of `eclasses`, counting how often each
+    /// instruction runs and fails.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// egraph.add_expr(&"(+ x (* x y))".parse().unwrap());
+    /// egraph.add_expr(&"(+ x (* z y))".parse().unwrap());
+    /// egraph.add_expr(&"(+ x y)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let pattern: Pattern<SymbolLang> = "(+ ?a (* ?a ?b))".parse().unwrap();
+    /// let profile = pattern.profile(&egraph);
+    /// // bind (+ ..) ran on all three e-classes with a +,
+    /// // bind (* ..) failed on the one without a *,
+    /// // and compare failed on the one with a different ?a
+    /// assert_eq!(profile.executed, vec![3, 3, 2]);
+    /// assert_eq!(profile.failed, vec![0, 1, 1]);
+    /// assert_eq!(profile.matches, 1);
+    /// println!("{}", pattern.program().display_with_profile(&profile));
+    /// ```
+    pub fn profile<A>(
+        &self,
+        egraph: &EGraph<L, A>,
+        eclasses: impl IntoIterator<Item = Id>,
+    ) -> ProgramProfile
+    where
+        A: Analysis<L>,
+    {
+        let n = self.instructions.len();
+        let mut machine = Machine {
+            profile: Some(ProgramProfile {
+                executed: vec![0; n],
+                failed: vec![0; n],
+                matches: 0,
+            }),
+            ..Default::default()
+        };
+        let mut matches = 0;
+        for eclass in eclasses {
+            machine.reg.clear();
+            matches += self
+                .run_machine(&mut machine, egraph, eclass, usize::MAX)
+                .len();
+        }
+        let mut profile = machine.profile.unwrap();
+        profile.matches = matches;
+        profile
+    }
+
+    fn run_machine<A>(
         &self,
+        machine: &mut Machine,
         egraph: &EGraph<L, A>,
         eclass: Id,
         mut limit: usize,
@@ -307,7 +835,6 @@ impl<L: Language> Program<L> {
             return vec![];
         }
 
-        let mut machine = Machine::default();
         assert_eq!(machine.reg.len(), 0);
         machine.reg.push(eclass);
 
@@ -318,21 +845,11 @@ impl<L: Language> Program<L> {
                 &self.instructions,
                 &self.subst,
                 &mut |machine, subst| {
-                    if !egraph.analysis.allow_ematching_cycles() {
-                        if let Some((first, rest)) = machine.reg.split_first() {
-                            if rest.contains(first) {
-                                return Ok(());
-                            }
-                        }
-                    }
-
-                    let subst_vec = subst
-                        .vec
-                        .iter()
-                        // HACK we are reusing Ids here, this is bad
-                        .map(|(v, reg_id)| (*v, machine.reg(Reg(usize::from(*reg_id) as u32))))
-                        .collect();
-                    matches.push(Subst { vec: subst_vec });
+                    let subst = match machine.instantiate(egraph, subst) {
+                        Some(subst) => subst,
+                        None => return Ok(()),
+                    };
+                    matches.push(subst);
                     limit -= 1;
                     if limit != 0 {
                         Ok(())
@@ -347,3 +864,210 @@ impl<L: Language> Program<L> {
         matches
     }
 }
+
+impl<L: Language + Display> Program<L> {
+    /// Display the program like its [`Display`] implementation, with how
+    /// often each instruction ran and failed according to `profile`.
+    pub fn display_with_profile<'a>(&'a self, profile: &'a ProgramProfile) -> impl Display + 'a {
+        ProgramDisplay {
+            program: self,
+            profile: Some(profile),
+        }
+    }
+}
+
+struct ProgramDisplay<'a, L> {
+    program: &'a Program<L>,
+    profile: Option<&'a ProgramProfile>,
+}
+
+impl<L: Language + Display> Display for ProgramDisplay<'_, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let vars: Vec<String> = self
+            .program
+            .subst
+            .vec
+            .iter()
+            // the subst holds register numbers, not ids
+            .map(|(v, r)| format!("{} = r{}", v, r))
+            .chain(
+                self.program
+                    .subst
+                    .seqs
+                    .iter()
+                    .map(|(v, seq)| format!("{}... = s{}", v, seq[0])),
+            )
+            .chain(
+                self.program
+                    .subst
+                    .leaves
+                    .iter()
+                    .map(|(v, r)| format!("{}! = r{}", v, r)),
+            )
+            .collect();
+        writeln!(f, "{}", vars.join(", "))?;
+        for (i, instruction) in self.program.instructions.iter().enumerate() {
+            write!(f, "{}: {}", i, instruction)?;
+            if let Some(profile) = self.profile {
+                write!(
+                    f,
+                    "  [ran {}, failed {}]",
+                    profile.executed[i], profile.failed[i]
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<L: Language + Display> Display for Program<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let display = ProgramDisplay {
+            program: self,
+            profile: None,
+        };
+        Display::fmt(&display, f)
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+impl<L: Language + Display> Display for Instruction<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Bind { node, i, out } => {
+                write!(f, "bind {} ", i)?;
+                if node.is_leaf() {
+                    return write!(f, "{}", node);
+                }
+                write!(f, "({}", node)?;
+                for child in 0..node.len() {
+                    write!(f, " {}", Reg(out.0 + child as u32))?;
+                }
+                write!(f, ")")
+            }
+            Instruction::Compare { i, j } => write!(f, "compare {} {}", i, j),
+            Instruction::BindSeq {
+                node,
+                i,
+                out,
+                at,
+                seq,
+            } => {
+                write!(f, "bind {} ({}", i, node)?;
+                let mut regs = (out.0..).map(Reg);
+                for child in 0..node.len() {
+                    if child == *at {
+                        write!(f, " s{}...", seq)?;
+                    } else {
+                        write!(f, " {}", regs.next().unwrap())?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Instruction::CompareSeq { i, j } => write!(f, "compare s{} s{}", i, j),
+            Instruction::Leaf { i, variant, out } => write!(f, "leaf {} {} {}", i, variant, out),
+            Instruction::Lookup { term, i } => {
+                fn term_string<L: Language + Display>(term: &[ENodeOrReg<L>], id: usize) -> String {
+                    match &term[id] {
+                        ENodeOrReg::Reg(r) => r.to_string(),
+                        ENodeOrReg::ENode(n) if n.is_leaf() => n.to_string(),
+                        ENodeOrReg::ENode(n) => {
+                            let mut s = format!("({}", n);
+                            for &child in n.children() {
+                                s.push(' ');
+                                s.push_str(&term_string(term, usize::from(child)));
+                            }
+                            s.push(')');
+                            s
+                        }
+                    }
+                }
+                write!(f, "lookup {} {}", i, term_string(term, term.len() - 1))
+            }
+            Instruction::Scan { out } => write!(f, "scan {}", out),
+            Instruction::Guard { i, guard } => write!(f, "guard {} {}", i, guard),
+        }
+    }
+}
+
+impl<L: Language> ProgramTrie<L> {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: vec![TrieNode {
+                instruction: None,
+                children: vec![],
+                programs: vec![],
+            }],
+        }
+    }
+
+    /// Adds `program` to the trie, sharing as many of its leading
+    /// instructions as possible with the programs already in it.
+    /// Matches of `program` are reported with `index`.
+    pub(crate) fn insert(&mut self, index: usize, program: &Program<L>) {
+        let mut node = 0;
+        for instruction in &program.instructions {
+            let existing = self.nodes[node]
+                .children
+                .iter()
+                .copied()
+                .find(|&c| self.nodes[c].instruction.as_ref() == Some(instruction));
+            node = match existing {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode {
+                        instruction: Some(instruction.clone()),
+                        children: vec![],
+                        programs: vec![],
+                    });
+                    self.nodes[node].children.push(child);
+                    child
+                }
+            };
+        }
+        let programs = &mut self.nodes[node].programs;
+        programs.push((index, program.subst.clone(), program.n_regs));
+    }
+
+    /// The number of instructions in the trie.
+    pub(crate) fn n_instructions(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Runs every program in the trie on `eclass`, calling `yield_fn`
+    /// with the index of the program and the match for each match found.
+    pub(crate) fn run<A>(
+        &self,
+        egraph: &EGraph<L, A>,
+        eclass: Id,
+        yield_fn: &mut impl FnMut(usize, Subst),
+    ) where
+        A: Analysis<L>,
+    {
+        assert!(egraph.clean, "Tried to search a dirty e-graph!");
+
+        let mut machine = Machine::default();
+        machine.reg.push(eclass);
+        let _ = machine.run_trie(egraph, self, 0, &mut |machine, index, subst| {
+            if let Some(subst) = machine.instantiate(egraph, subst) {
+                yield_fn(index, subst);
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+impl<L: Language> Program<L> {
+    /// The number of instructions in the program.
+    pub(crate) fn n_instructions(&self) -> usize {
+        self.instructions.len()
+    }
+}
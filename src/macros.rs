@@ -102,7 +102,7 @@ macro_rules! define_language {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($($gen),*)?] { $($variants)* }
             [$($($where)*)?]
-            -> {} {} {} {} {} {}
+            -> {} {} {} {} {} {} {} {}
         );
     };
 }
@@ -114,7 +114,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      $decl:tt {$($matches:tt)*} $children:tt $children_mut:tt
-     $display:tt {$($from_op:tt)*}
+     $display:tt {$($from_op:tt)*} {$($with_children:tt)*} {$($leaf:ident)*}
     ) => {
         $(#[$meta])*
         #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -136,6 +136,32 @@ macro_rules! __define_language {
 
             fn children(&self) -> &[$crate::Id] { match self $children }
             fn children_mut(&mut self) -> &mut [$crate::Id] { match self $children_mut }
+
+            fn with_children(&self, children: ::std::vec::Vec<$crate::Id>) -> ::std::option::Option<Self> {
+                match (self, children) {
+                    $($with_children)*
+                    _ => None,
+                }
+            }
+
+            #[allow(unreachable_patterns)]
+            fn leaf_variant(&self) -> ::std::option::Option<&'static str> {
+                match self {
+                    $($name::$leaf(_) => Some(stringify!($leaf)),)*
+                    _ => None,
+                }
+            }
+
+            #[allow(unreachable_patterns)]
+            fn leaf_data(&self) -> ::std::option::Option<&dyn ::std::any::Any>
+            where
+                Self: 'static,
+            {
+                match self {
+                    $($name::$leaf(data) => Some(data),)*
+                    _ => None,
+                }
+            }
         }
 
         impl<$($gen),*> ::std::fmt::Display for $name <$($gen),*> where $($where)* {
@@ -166,7 +192,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_children:tt)* } { $($leaf:ident)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -179,6 +205,8 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant => &mut [], }
             { $($display)*       ($name::$variant, f) => f.write_str($string), }
             { $($from_op)*       ($string, children) if children.is_empty() => Ok($name::$variant), }
+            { $($with_children)* ($name::$variant, children) if children.is_empty() => Some($name::$variant), }
+            { $($leaf)* }
         );
     };
 
@@ -190,7 +218,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_children:tt)* } { $($leaf:ident)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -207,6 +235,11 @@ macro_rules! __define_language {
                   Ok($name::$variant(children))
               },
             }
+            { $($with_children)* ($name::$variant(..), children) if <$ids as $crate::LanguageChildren>::can_be_length(children.len()) => {
+                  Some($name::$variant(<$ids as $crate::LanguageChildren>::from_vec(children)))
+              },
+            }
+            { $($leaf)* }
         );
     };
 
@@ -218,7 +251,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_children:tt)* } { $($leaf:ident)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -231,6 +264,8 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant(_data) => &mut [], }
             { $($display)*       ($name::$variant(data), f) => ::std::fmt::Display::fmt(data, f), }
             { $($from_op)*       (op, children) if op.parse::<$data>().is_ok() && children.is_empty() => Ok($name::$variant(op.parse().unwrap())), }
+            { $($with_children)* ($name::$variant(data), children) if children.is_empty() => Some($name::$variant(data.clone())), }
+            { $($leaf)*          $variant }
         );
     };
 
@@ -242,7 +277,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($with_children:tt)* } { $($leaf:ident)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen)*]
@@ -260,6 +295,11 @@ macro_rules! __define_language {
                   Ok($name::$variant(data, children))
               },
             }
+            { $($with_children)* ($name::$variant(data, _), children) if <$ids as $crate::LanguageChildren>::can_be_length(children.len()) => {
+                  Some($name::$variant(data.clone(), <$ids as $crate::LanguageChildren>::from_vec(children)))
+              },
+            }
+            { $($leaf)* }
         );
     };
 }
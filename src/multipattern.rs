@@ -1,4 +1,5 @@
 use std::str::FromStr;
+use std::sync::OnceLock;
 use thiserror::Error;
 
 use crate::*;
@@ -21,12 +22,25 @@ use crate::*;
 /// searcher are unioned with that e-class.
 ///
 /// Multipatterns currently do not support the explanations feature.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub struct MultiPattern<L> {
-    asts: Vec<(Var, PatternAst<L>)>,
+    pub(crate) asts: Vec<(Var, PatternAst<L>)>,
     program: machine::Program<L>,
+    /// For [`Searcher::search_touched`], a program for each pattern that
+    /// searches it first, compiled the first time they are needed.
+    /// Empty if some pattern is a bare variable.
+    rotated_programs: OnceLock<Vec<machine::Program<L>>>,
 }
 
+impl<L: PartialEq> PartialEq for MultiPattern<L> {
+    fn eq(&self, other: &Self) -> bool {
+        // the programs are compiled from the patterns
+        self.asts == other.asts
+    }
+}
+
+impl<L: Eq> Eq for MultiPattern<L> {}
+
 impl<L: Language> MultiPattern<L> {
     /// Creates a new multipattern, binding the given patterns to the corresponding variables.
     ///
@@ -52,8 +66,41 @@ impl<L: Language> MultiPattern<L> {
     /// assert_eq!(multipattern.n_matches(&egraph), 2);
     /// ```
     pub fn new(asts: Vec<(Var, PatternAst<L>)>) -> Self {
+        for (_, pat) in &asts {
+            crate::pattern::check_seqs(pat);
+        }
         let program = machine::Program::compile_from_multi_pat(&asts);
-        Self { asts, program }
+        Self {
+            asts,
+            program,
+            rotated_programs: OnceLock::new(),
+        }
+    }
+
+    fn rotated_programs(&self) -> &[machine::Program<L>] {
+        self.rotated_programs.get_or_init(|| {
+            let is_bare = |pat: &PatternAst<L>| {
+                matches!(
+                    **pat,
+                    [ENodeOrVar::Var(_)] | [ENodeOrVar::Guarded(..)] | [ENodeOrVar::Leaf(..)]
+                )
+            };
+            if self.asts.iter().any(|(_, pat)| is_bare(pat)) {
+                return vec![];
+            }
+            (0..self.asts.len())
+                .map(|i| {
+                    let mut rotated = self.asts.clone();
+                    rotated[..=i].rotate_right(1);
+                    machine::Program::compile_from_multi_pat(&rotated)
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the [`Program`] this multipattern is compiled to.
+    pub fn program(&self) -> &Program<L> {
+        &self.program
     }
 }
 
@@ -101,6 +148,52 @@ impl<L: Language + FromOp> FromStr for MultiPattern<L> {
 }
 
 impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
+    /// Runs one program per pattern to find where the first pattern
+    /// matches in a match involving a touched eclass, then searches
+    /// only there.
+    /// If one of the patterns is a bare variable, the analysis does not
+    /// [allow e-matching cycles](Analysis::allow_ematching_cycles()), or
+    /// at least half of the eclasses were touched,
+    /// this searches the whole egraph instead, but still only returns
+    /// matches that involve a touched eclass.
+    fn search_touched(&self, egraph: &EGraph<L, A>, generation: usize) -> Vec<SearchMatches<L>> {
+        let touches = |subst: &Subst| {
+            self.asts
+                .iter()
+                .any(|(_, pat)| pattern::touches(egraph, pat, subst, generation))
+        };
+
+        let touched: Vec<Id> = egraph.touched_since(generation).map(|c| c.id).collect();
+        let mut matches = if 2 * touched.len() >= egraph.number_of_classes()
+            || !egraph.analysis.allow_ematching_cycles()
+            || self.rotated_programs().is_empty()
+        {
+            self.search(egraph)
+        } else {
+            let first_var = self.asts[0].0;
+            let mut roots = HashSet::default();
+            for ((_, pat), program) in self.asts.iter().zip(self.rotated_programs()) {
+                let height = pattern::height(pat);
+                for id in egraph.ancestors_within(touched.iter().copied(), height) {
+                    for subst in program.run_with_limit(egraph, id, usize::MAX) {
+                        if touches(&subst) {
+                            roots.insert(subst[first_var]);
+                        }
+                    }
+                }
+            }
+            let mut roots: Vec<Id> = roots.into_iter().collect();
+            roots.sort_unstable();
+            rewrite::search_eclasses_with_limit(self, egraph, roots, usize::MAX)
+        };
+
+        for m in &mut matches {
+            m.substs.retain(&touches);
+        }
+        matches.retain(|m| !m.substs.is_empty());
+        matches
+    }
+
     fn search_eclass_with_limit(
         &self,
         egraph: &EGraph<L, A>,
@@ -110,7 +203,9 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
         match self.asts.as_slice() {
             [] => panic!("empty multipattern"),
             [(_var, pat), ..] => {
-                if let [ENodeOrVar::Var(_)] = **pat {
+                if let [ENodeOrVar::Var(_)] | [ENodeOrVar::Guarded(..)] | [ENodeOrVar::Leaf(..)] =
+                    **pat
+                {
                     panic!(
                         "Bare cannot be first pattern variable in multipattern: {:?}",
                         self.asts
@@ -135,7 +230,11 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
         for (v, pat) in &self.asts {
             vars.push(*v);
             for n in pat {
-                if let ENodeOrVar::Var(v) = n {
+                if let ENodeOrVar::Var(v)
+                | ENodeOrVar::Guarded(v, _)
+                | ENodeOrVar::Seq(v)
+                | ENodeOrVar::Leaf(v, _) = n
+                {
                     vars.push(*v)
                 }
             }
@@ -191,7 +290,11 @@ impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
         let mut vars = vec![];
         for (bv, pat) in &self.asts {
             for n in pat {
-                if let ENodeOrVar::Var(v) = n {
+                if let ENodeOrVar::Var(v)
+                | ENodeOrVar::Guarded(v, _)
+                | ENodeOrVar::Seq(v)
+                | ENodeOrVar::Leaf(v, _) = n
+                {
                     // using vars that are already bound doesn't count
                     if !bound_vars.contains(v) {
                         vars.push(*v)
@@ -303,6 +406,79 @@ mod tests {
         assert_ne!(runner.egraph.find(x1), runner.egraph.find(z1));
     }
 
+    #[test]
+    fn search_touched() {
+        crate::init_logger();
+        let mut egraph = EGraph::default();
+        egraph.add_string("(f a b)");
+        egraph.add_string("(g a c)");
+        egraph.add_string("(h (f c c) (g b b))");
+        egraph.rebuild();
+
+        let searchers: Vec<Box<dyn Searcher<S, ()>>> = vec![
+            Box::new("(f ?x ?y)".parse::<Pattern<S>>().unwrap()),
+            Box::new("(h (f ?x ?x) ?y)".parse::<Pattern<S>>().unwrap()),
+            Box::new(
+                "?v = (f ?x ?y), ?w = (g ?x ?z)"
+                    .parse::<MultiPattern<S>>()
+                    .unwrap(),
+            ),
+            Box::new(
+                "?v = (f ?x ?y), ?w = (g ?y ?x)"
+                    .parse::<MultiPattern<S>>()
+                    .unwrap(),
+            ),
+        ];
+
+        type Match = (Id, Vec<(Var, Id)>);
+        let canonical = |egraph: &EGraph, ms: &[Match]| -> std::collections::BTreeSet<Match> {
+            ms.iter()
+                .map(|(eclass, subst)| {
+                    let mut subst: Vec<_> =
+                        subst.iter().map(|&(v, id)| (v, egraph.find(id))).collect();
+                    subst.sort();
+                    (egraph.find(*eclass), subst)
+                })
+                .collect()
+        };
+        let flatten = |ms: Vec<SearchMatches<S>>| -> Vec<Match> {
+            ms.into_iter()
+                .flat_map(|m| {
+                    let eclass = m.eclass;
+                    m.substs.into_iter().map(move |s| (eclass, s.vec.to_vec()))
+                })
+                .collect()
+        };
+
+        let old: Vec<Vec<Match>> = searchers
+            .iter()
+            .map(|s| flatten(s.search(&egraph)))
+            .collect();
+
+        let generation = egraph.generation();
+        egraph.add_string("(g b a)");
+        egraph.add_string("(f c d)");
+        let b = egraph.add_string("b");
+        let c = egraph.add_string("c");
+        egraph.union(b, c);
+        egraph.rebuild();
+
+        for (searcher, old) in searchers.iter().zip(old) {
+            let old = canonical(&egraph, &old);
+            let all = canonical(&egraph, &flatten(searcher.search(&egraph)));
+            let touched = flatten(searcher.search_touched(&egraph, generation));
+            let touched = canonical(&egraph, &touched);
+            assert!(touched.is_subset(&all));
+            assert!(all.difference(&old).all(|m| touched.contains(m)));
+            assert!(!touched.is_empty());
+        }
+
+        let generation = egraph.generation();
+        for searcher in &searchers {
+            assert!(searcher.search_touched(&egraph, generation).is_empty());
+        }
+    }
+
     #[test]
     fn bare_var() {
         let mut g = EGraph::default();
@@ -61,12 +61,24 @@ use crate::*;
 /// assert_eq!(matched_eclasses, vec![a22, a11]);
 /// ```
 ///
+/// Operators with a variable number of children, like `"list" = List(Vec<Id>)`,
+/// can be matched with a sequence variable written `?xs...`, which binds
+/// any number of consecutive children, as in `(list ?head ?tail...)`.
+/// See [`ENodeOrVar::Seq`] and [`Subst::get_seq`].
+///
+/// A variable written `?n!Num` only matches e-classes with a leaf of the
+/// variant `Num`, like `Num(2)` in a language made with
+/// [`define_language!`], and also binds the data of that leaf, giving one
+/// match for each such leaf.
+/// Appliers and conditions can read the data with [`Subst::leaf`].
+/// See [`Language::leaf_variant`] for how variants are named.
+///
 /// [`FromStr`]: std::str::FromStr
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Pattern<L> {
     /// The actual pattern as a [`RecExpr`]
     pub ast: PatternAst<L>,
-    program: machine::Program<L>,
+    pub(crate) program: machine::Program<L>,
 }
 
 /// A [`RecExpr`] that represents a
@@ -94,6 +106,18 @@ impl<L: Language> PatternAst<L> {
                     let i = vars.len();
                     ENodeOrVar::Var(*vars.entry(*v).or_insert_with(|| mkvar(i)))
                 }
+                ENodeOrVar::Guarded(v, guard) => {
+                    let i = vars.len();
+                    ENodeOrVar::Guarded(*vars.entry(*v).or_insert_with(|| mkvar(i)), *guard)
+                }
+                ENodeOrVar::Seq(v) => {
+                    let i = vars.len();
+                    ENodeOrVar::Seq(*vars.entry(*v).or_insert_with(|| mkvar(i)))
+                }
+                ENodeOrVar::Leaf(v, variant) => {
+                    let i = vars.len();
+                    ENodeOrVar::Leaf(*vars.entry(*v).or_insert_with(|| mkvar(i)), *variant)
+                }
             });
         }
 
@@ -103,8 +127,12 @@ impl<L: Language> PatternAst<L> {
 
 impl<L: Language> Pattern<L> {
     /// Creates a new pattern from the given pattern ast.
+    ///
+    /// This panics if a sequence variable (like `?xs...`) is not a child
+    /// of an e-node, or an e-node has more than one.
     pub fn new(ast: PatternAst<L>) -> Self {
         let ast = ast.compact();
+        check_seqs(&ast);
         let program = machine::Program::compile_from_pat(&ast);
         Pattern { ast, program }
     }
@@ -113,7 +141,11 @@ impl<L: Language> Pattern<L> {
     pub fn vars(&self) -> Vec<Var> {
         let mut vars = vec![];
         for n in &self.ast {
-            if let ENodeOrVar::Var(v) = n {
+            if let ENodeOrVar::Var(v)
+            | ENodeOrVar::Guarded(v, _)
+            | ENodeOrVar::Seq(v)
+            | ENodeOrVar::Leaf(v, _) = n
+            {
                 if !vars.contains(v) {
                     vars.push(*v)
                 }
@@ -121,6 +153,28 @@ impl<L: Language> Pattern<L> {
         }
         vars
     }
+
+    /// Returns the [`Program`] this pattern is compiled to.
+    pub fn program(&self) -> &Program<L> {
+        &self.program
+    }
+
+    /// Runs this pattern's [`Program`] on the e-classes
+    /// [`search`](Searcher::search()) would, counting how often each
+    /// instruction runs and fails.
+    /// See [`Program::profile`].
+    pub fn profile<N: Analysis<L>>(&self, egraph: &EGraph<L, N>) -> ProgramProfile {
+        match self.ast.last().unwrap() {
+            ENodeOrVar::ENode(e) => match egraph.classes_for_op(&e.discriminant()) {
+                Some(ids) => self.program.profile(egraph, ids),
+                None => self.program.profile(egraph, None),
+            },
+            ENodeOrVar::Var(_)
+            | ENodeOrVar::Guarded(..)
+            | ENodeOrVar::Seq(_)
+            | ENodeOrVar::Leaf(..) => self.program.profile(egraph, egraph.classes().map(|c| c.id)),
+        }
+    }
 }
 
 impl<L: Language + Display> Pattern<L> {
@@ -133,11 +187,26 @@ impl<L: Language + Display> Pattern<L> {
 /// The language of [`Pattern`]s.
 ///
 #[derive(Debug, Hash, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[non_exhaustive]
 pub enum ENodeOrVar<L> {
     /// An enode from the underlying [`Language`]
     ENode(L),
     /// A pattern variable
     Var(Var),
+    /// A pattern variable that only matches e-classes that pass the named
+    /// guard, written `?x:guard`.
+    /// See [`EGraph::with_guard`].
+    Guarded(Var, Symbol),
+    /// A sequence variable, written `?xs...`, that matches any number of
+    /// consecutive children of an e-node.
+    /// It may only be a child of an e-node, and each e-node may have at
+    /// most one; see [`Language::with_children`].
+    Seq(Var),
+    /// A pattern variable, written `?n!Num`, that only matches e-classes
+    /// with a leaf of the named variant, and also binds the data of that
+    /// leaf, with one match for each one.
+    /// See [`Language::leaf_variant`] and [`Subst::leaf`].
+    Leaf(Var, Symbol),
 }
 
 /// The discriminant for the language of [`Pattern`]s.
@@ -154,7 +223,10 @@ impl<L: Language> Language for ENodeOrVar<L> {
     fn discriminant(&self) -> Self::Discriminant {
         match self {
             ENodeOrVar::ENode(n) => ENodeOrVarDiscriminant::ENode(n.discriminant()),
-            ENodeOrVar::Var(v) => ENodeOrVarDiscriminant::Var(*v),
+            ENodeOrVar::Var(v)
+            | ENodeOrVar::Guarded(v, _)
+            | ENodeOrVar::Seq(v)
+            | ENodeOrVar::Leaf(v, _) => ENodeOrVarDiscriminant::Var(*v),
         }
     }
 
@@ -165,14 +237,20 @@ impl<L: Language> Language for ENodeOrVar<L> {
     fn children(&self) -> &[Id] {
         match self {
             ENodeOrVar::ENode(n) => n.children(),
-            ENodeOrVar::Var(_) => &[],
+            ENodeOrVar::Var(_)
+            | ENodeOrVar::Guarded(..)
+            | ENodeOrVar::Seq(_)
+            | ENodeOrVar::Leaf(..) => &[],
         }
     }
 
     fn children_mut(&mut self) -> &mut [Id] {
         match self {
             ENodeOrVar::ENode(n) => n.children_mut(),
-            ENodeOrVar::Var(_) => &mut [],
+            ENodeOrVar::Var(_)
+            | ENodeOrVar::Guarded(..)
+            | ENodeOrVar::Seq(_)
+            | ENodeOrVar::Leaf(..) => &mut [],
         }
     }
 }
@@ -182,6 +260,9 @@ impl<L: Language + Display> Display for ENodeOrVar<L> {
         match self {
             Self::ENode(node) => Display::fmt(node, f),
             Self::Var(var) => Display::fmt(var, f),
+            Self::Guarded(var, guard) => write!(f, "{}:{}", var, guard),
+            Self::Seq(var) => write!(f, "{}...", var),
+            Self::Leaf(var, variant) => write!(f, "{}!{}", var, variant),
         }
     }
 }
@@ -194,6 +275,12 @@ pub enum ENodeOrVarParseError<E> {
     #[error("tried to parse pattern variable {0:?} as an operator")]
     UnexpectedVar(String),
 
+    #[error("pattern variable {0:?} has an empty guard")]
+    EmptyGuard(String),
+
+    #[error("leaf variable {0:?} does not name a variant")]
+    EmptyVariant(String),
+
     #[error(transparent)]
     BadOp(E),
 }
@@ -205,10 +292,24 @@ impl<L: FromOp> FromOp for ENodeOrVar<L> {
         use ENodeOrVarParseError::*;
 
         if op.starts_with('?') && op.len() > 1 {
-            if children.is_empty() {
-                op.parse().map(Self::Var).map_err(BadVar)
-            } else {
+            if !children.is_empty() {
                 Err(UnexpectedVar(op.to_owned()))
+            } else if let Some(var) = op.strip_suffix("...") {
+                var.parse().map(Self::Seq).map_err(BadVar)
+            } else if let Some((var, variant)) = op.split_once('!') {
+                if variant.is_empty() {
+                    return Err(EmptyVariant(op.to_owned()));
+                }
+                let var = var.parse().map_err(BadVar)?;
+                Ok(Self::Leaf(var, variant.into()))
+            } else if let Some((var, guard)) = op.split_once(':') {
+                if guard.is_empty() {
+                    return Err(EmptyGuard(op.to_owned()));
+                }
+                let var = var.parse().map_err(BadVar)?;
+                Ok(Self::Guarded(var, guard.into()))
+            } else {
+                op.parse().map(Self::Var).map_err(BadVar)
             }
         } else {
             L::from_op(op, children).map(Self::ENode).map_err(BadOp)
@@ -256,7 +357,10 @@ impl<L: Language> TryFrom<PatternAst<L>> for RecExpr<L> {
         ast.into_iter()
             .map(|n| match n {
                 ENodeOrVar::ENode(n) => Ok(n),
-                ENodeOrVar::Var(v) => Err(v),
+                ENodeOrVar::Var(v)
+                | ENodeOrVar::Guarded(v, _)
+                | ENodeOrVar::Seq(v)
+                | ENodeOrVar::Leaf(v, _) => Err(v),
             })
             .collect()
     }
@@ -306,7 +410,10 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for Pattern<L> {
                     Some(ids) => rewrite::search_eclasses_with_limit(self, egraph, ids, limit),
                 }
             }
-            ENodeOrVar::Var(_) => rewrite::search_eclasses_with_limit(
+            ENodeOrVar::Var(_)
+            | ENodeOrVar::Guarded(..)
+            | ENodeOrVar::Seq(_)
+            | ENodeOrVar::Leaf(..) => rewrite::search_eclasses_with_limit(
                 self,
                 egraph,
                 egraph.classes().map(|e| e.id),
@@ -315,6 +422,24 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for Pattern<L> {
         }
     }
 
+    fn search_touched(&self, egraph: &EGraph<L, A>, generation: usize) -> Vec<SearchMatches<L>> {
+        let touched = egraph.touched_since(generation).map(|class| class.id);
+        let mut roots = egraph.ancestors_within(touched, height(&self.ast));
+        if let ENodeOrVar::ENode(e) = self.ast.last().unwrap() {
+            let key = e.discriminant();
+            roots.retain(|&id| egraph[id].nodes.iter().any(|n| n.discriminant() == key));
+        }
+        roots.sort_unstable();
+
+        let mut matches = rewrite::search_eclasses_with_limit(self, egraph, roots, usize::MAX);
+        for m in &mut matches {
+            m.substs
+                .retain(|subst| touches(egraph, &self.ast, subst, generation));
+        }
+        matches.retain(|m| !m.substs.is_empty());
+        matches
+    }
+
     fn search_eclass_with_limit(
         &self,
         egraph: &EGraph<L, A>,
@@ -410,6 +535,141 @@ where
     }
 }
 
+/// The length of the longest path from the root of `pat` to a leaf.
+pub(crate) fn height<L: Language>(pat: &PatternAst<L>) -> usize {
+    let nodes = pat.as_ref();
+    let mut depths = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate().rev() {
+        for &child in node.children() {
+            let child = usize::from(child);
+            depths[child] = depths[child].max(depths[i] + 1);
+        }
+    }
+    depths.into_iter().max().unwrap_or(0)
+}
+
+/// Whether the match of `pat` given by `subst` involves an eclass touched
+/// in or after `generation`.
+pub(crate) fn touches<L: Language, A: Analysis<L>>(
+    egraph: &EGraph<L, A>,
+    pat: &PatternAst<L>,
+    subst: &Subst,
+    generation: usize,
+) -> bool {
+    let mut ids: Vec<Id> = Vec::with_capacity(pat.len());
+    for node in pat.as_ref() {
+        let id = match node {
+            ENodeOrVar::Var(v) | ENodeOrVar::Guarded(v, _) | ENodeOrVar::Leaf(v, _) => subst[*v],
+            ENodeOrVar::Seq(v) => {
+                if subst
+                    .get_seq(*v)
+                    .unwrap_or_default()
+                    .iter()
+                    .any(|&id| egraph[id].touched >= generation)
+                {
+                    return true;
+                }
+                ids.push(Id::from(0));
+                continue;
+            }
+            ENodeOrVar::ENode(n) => {
+                let n = instantiate_node(pat.as_ref(), n, &ids, subst);
+                match egraph.lookup(n) {
+                    Some(id) => id,
+                    // not a match after all, let the caller keep it
+                    None => return true,
+                }
+            }
+        };
+        if egraph[id].touched >= generation {
+            return true;
+        }
+        ids.push(id);
+    }
+    false
+}
+
+/// Panics if a sequence variable in `pat` is not a child of an e-node,
+/// or an e-node has more than one.
+pub(crate) fn check_seqs<L: Language>(pat: &PatternAst<L>) {
+    let nodes = pat.as_ref();
+    if let Some(ENodeOrVar::Seq(v)) = nodes.last() {
+        panic!("Sequence var {} must be a child of an enode", v);
+    }
+    for node in nodes {
+        let n_seqs = node
+            .children()
+            .iter()
+            .filter(|&&c| matches!(nodes[usize::from(c)], ENodeOrVar::Seq(_)))
+            .count();
+        if n_seqs > 1 {
+            panic!(
+                "An enode can have at most one sequence var, but found {}",
+                n_seqs
+            );
+        }
+    }
+}
+
+/// The variables in `pat`, with whether each one is a sequence variable.
+pub(crate) fn var_kinds<L: Language>(
+    pat: &PatternAst<L>,
+) -> impl Iterator<Item = (Var, bool)> + '_ {
+    pat.as_ref().iter().filter_map(|n| match n {
+        ENodeOrVar::Var(v) | ENodeOrVar::Guarded(v, _) | ENodeOrVar::Leaf(v, _) => {
+            Some((*v, false))
+        }
+        ENodeOrVar::Seq(v) => Some((*v, true)),
+        ENodeOrVar::ENode(_) => None,
+    })
+}
+
+/// The position of the sequence variable among the children of `node`,
+/// if it has one.
+pub(crate) fn seq_position<L: Language>(pat: &[ENodeOrVar<L>], node: &L) -> Option<usize> {
+    node.children()
+        .iter()
+        .position(|&c| matches!(pat[usize::from(c)], ENodeOrVar::Seq(_)))
+}
+
+/// Whether `enode` could match `node`, one of whose children is a
+/// sequence variable: they have the same operator, and `enode` has
+/// enough children for the rest of the children of `node`.
+pub(crate) fn matches_variadic<L: Language>(node: &L, enode: &L) -> bool {
+    enode.len() + 1 >= node.len()
+        && node
+            .with_children(enode.children().to_vec())
+            .map_or(false, |n| n.matches(enode))
+}
+
+/// Instantiates `node`, an e-node of `pat`, with the e-classes in `ids`
+/// for its children and the e-classes `subst` binds for its sequence
+/// variable.
+pub(crate) fn instantiate_node<L: Language>(
+    pat: &[ENodeOrVar<L>],
+    node: &L,
+    ids: &[Id],
+    subst: &Subst,
+) -> L {
+    let seq = match seq_position(pat, node) {
+        None => return node.clone().map_children(|child| ids[usize::from(child)]),
+        Some(seq) => seq,
+    };
+    let mut children = Vec::with_capacity(node.len());
+    for (i, &child) in node.children().iter().enumerate() {
+        match &pat[usize::from(child)] {
+            ENodeOrVar::Seq(v) if i == seq => match subst.get_seq(*v) {
+                Some(seq_ids) => children.extend_from_slice(seq_ids),
+                None => panic!("Sequence var '{}' not found in {:?}", v, subst),
+            },
+            _ => children.push(ids[usize::from(child)]),
+        }
+    }
+    let len = children.len();
+    node.with_children(children)
+        .unwrap_or_else(|| panic!("{:?} can not have {} children", node, len))
+}
+
 pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
     ids: &mut [Id],
     pat: &[ENodeOrVar<L>],
@@ -421,9 +681,11 @@ pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
 
     for (i, pat_node) in pat.iter().enumerate() {
         let id = match pat_node {
-            ENodeOrVar::Var(w) => subst[*w],
+            ENodeOrVar::Var(w) | ENodeOrVar::Guarded(w, _) | ENodeOrVar::Leaf(w, _) => subst[*w],
+            // only used by the e-node it is a child of
+            ENodeOrVar::Seq(_) => Id::from(0),
             ENodeOrVar::ENode(e) => {
-                let n = e.clone().map_children(|child| ids[usize::from(child)]);
+                let n = instantiate_node(pat, e, ids, subst);
                 trace!("adding: {:?}", n);
                 egraph.add(n)
             }
@@ -537,4 +799,238 @@ mod tests {
             assert_eq!(m1.substs.len(), usize::min(limit, match_size));
         }
     }
+
+    #[test]
+    fn program_display() {
+        let p: Pattern<S> = "(f ?a (g ?a 0))".parse().unwrap();
+        assert_eq!(
+            p.program().to_string(),
+            "?a = r1\n0: bind r0 (f r1 r2)\n1: lookup r2 (g r1 0)\n"
+        );
+        let m: MultiPattern<S> = "?x = (f ?a), ?y = (g ?a)".parse().unwrap();
+        assert_eq!(
+            m.program().to_string(),
+            "?x = r0, ?a = r1, ?y = r2\n0: bind r0 (f r1)\n1: scan r2\n2: lookup r2 (g r1)\n"
+        );
+    }
+
+    #[test]
+    fn guards() {
+        type EGraph = crate::EGraph<S, bool>;
+        impl Analysis<S> for bool {
+            type Data = bool;
+            fn make(_egraph: &mut EGraph, enode: &S) -> bool {
+                enode.op.as_str().parse::<i32>().is_ok()
+            }
+            fn merge(&mut self, a: &mut bool, b: bool) -> DidMerge {
+                merge_max(a, b)
+            }
+        }
+
+        let mut egraph = EGraph::new(false).with_guard("const", |c: &bool| *c);
+        egraph.add_expr(&"(+ x 1)".parse().unwrap());
+        egraph.add_expr(&"(+ 2 3)".parse().unwrap());
+        egraph.add_expr(&"(+ 2 2)".parse().unwrap());
+        egraph.add_expr(&"(* (+ 2 y) 2)".parse().unwrap());
+        egraph.rebuild();
+
+        let n_matches = |s: &str| {
+            let pattern: Pattern<S> = s.parse().unwrap();
+            assert_eq!(pattern.to_string(), s);
+            let relational = RelationalPattern::from(pattern.clone());
+            let n = pattern.n_matches(&egraph);
+            assert_eq!(relational.n_matches(&egraph), n, "{}", s);
+            n
+        };
+        assert_eq!(n_matches("(+ ?a ?b)"), 4);
+        assert_eq!(n_matches("(+ ?a:const ?b)"), 3);
+        assert_eq!(n_matches("(+ ?a:const ?b:const)"), 2);
+        assert_eq!(n_matches("(+ ?a:const ?a)"), 1);
+        assert_eq!(n_matches("(* (+ ?a ?b) ?a:const)"), 1);
+        assert_eq!(n_matches("(* (+ ?a ?b:const) ?a)"), 0);
+        assert_eq!(n_matches("?a:const"), 3);
+
+        let multi: MultiPattern<S> = "?x = (+ ?a ?b), ?y = (* ?x ?b:const)".parse().unwrap();
+        assert!(multi.program().to_string().contains("guard"));
+        assert_eq!(multi.n_matches(&egraph), 0);
+        let multi: MultiPattern<S> = "?x = (+ ?a ?b), ?y = (* ?x ?a:const)".parse().unwrap();
+        assert_eq!(multi.n_matches(&egraph), 1);
+        assert_eq!(RelationalPattern::from(multi).n_matches(&egraph), 1);
+    }
+
+    #[test]
+    fn unknown_guard() {
+        let mut egraph = crate::EGraph::<S, ()>::default();
+        egraph.add_expr(&"(+ x 1)".parse().unwrap());
+        egraph.rebuild();
+        let pattern: Pattern<S> = "(+ ?a:nope ?b)".parse().unwrap();
+        assert_eq!(pattern.n_matches(&egraph), 0);
+        assert_eq!(RelationalPattern::from(pattern).n_matches(&egraph), 0);
+    }
+
+    #[test]
+    fn sequence_vars() {
+        crate::init_logger();
+        define_language! {
+            enum Lists {
+                Num(i32),
+                "list" = List(Vec<Id>),
+                "pair" = Pair([Id; 2]),
+            }
+        }
+
+        let mut egraph = crate::EGraph::<Lists, ()>::default();
+        let l123 = egraph.add_expr(&"(list 1 2 3)".parse().unwrap());
+        egraph.add_expr(&"(list 1)".parse().unwrap());
+        egraph.add_expr(&"(list)".parse().unwrap());
+        egraph.add_expr(&"(pair (list 1 2) (list 1 2))".parse().unwrap());
+        egraph.add_expr(&"(pair (list 1 2) (list 1 3))".parse().unwrap());
+        egraph.rebuild();
+
+        let pattern = |s: &str| {
+            let pattern: Pattern<Lists> = s.parse().unwrap();
+            assert_eq!(pattern.to_string(), s);
+            pattern
+        };
+        assert_eq!(pattern("(list ?xs...)").n_matches(&egraph), 5);
+        assert_eq!(pattern("(list ?a ?rest...)").n_matches(&egraph), 4);
+        assert_eq!(pattern("(list ?a ?b ?rest...)").n_matches(&egraph), 3);
+        assert_eq!(pattern("(list ?xs... 3)").n_matches(&egraph), 2);
+        assert_eq!(pattern("(list 1 ?xs... 3)").n_matches(&egraph), 2);
+        assert_eq!(
+            pattern("(pair (list ?xs...) (list ?xs...))").n_matches(&egraph),
+            1
+        );
+
+        let init = pattern("(list ?xs... ?last)");
+        assert_eq!(
+            init.program().to_string(),
+            "?last = r1, ?xs... = s0\n0: bind r0 (list s0... r1)\n"
+        );
+        let matches = init.search_eclass(&egraph, l123).unwrap();
+        let xs: Var = "?xs".parse().unwrap();
+        let expected: Vec<Id> = ["1", "2"]
+            .iter()
+            .map(|n| egraph.lookup(Lists::Num(n.parse().unwrap())).unwrap())
+            .collect();
+        assert_eq!(matches.substs[0].get_seq(xs), Some(&expected[..]));
+
+        let set: PatternSet<Lists> = ["(list ?a ?rest...)", "(list ?a ?b ?rest...)"]
+            .iter()
+            .map(|s| pattern(s))
+            .collect();
+        let counts: Vec<usize> = set
+            .search(&egraph)
+            .iter()
+            .map(|ms| ms.iter().map(|m| m.substs.len()).sum())
+            .collect();
+        assert_eq!(counts, vec![4, 3]);
+
+        let rules: Vec<Rewrite<Lists, ()>> =
+            vec![rewrite!("rotate"; "(list ?a ?rest...)" => "(list ?rest... ?a)")];
+        let mut runner = Runner::default()
+            .with_explanations_enabled()
+            .with_expr(&"(list 1 2 3)".parse().unwrap())
+            .run(&rules);
+        let start = "(list 1 2 3)".parse().unwrap();
+        let goal = "(list 3 1 2)".parse().unwrap();
+        assert_eq!(runner.egraph.equivs(&start, &goal).len(), 1);
+        runner
+            .egraph
+            .explain_equivalence(&start, &goal)
+            .check_proof(&rules);
+    }
+
+    #[test]
+    fn leaf_vars() {
+        crate::init_logger();
+        define_language! {
+            enum Math {
+                Num(i32),
+                "+" = Add([Id; 2]),
+                Symbol(Symbol),
+            }
+        }
+
+        struct Fold;
+        impl Applier<Math, ()> for Fold {
+            fn apply_one(
+                &self,
+                egraph: &mut crate::EGraph<Math, ()>,
+                eclass: Id,
+                subst: &Subst,
+                _searcher_ast: Option<&PatternAst<Math>>,
+                _rule_name: Symbol,
+            ) -> Vec<Id> {
+                let num = |v: &str| subst.leaf::<i32, _, _>(egraph, v.parse().unwrap()).copied();
+                match (num("?a"), num("?b")) {
+                    (Some(a), Some(b)) => {
+                        let id = egraph.add(Math::Num(a + b));
+                        if egraph.union(eclass, id) {
+                            vec![id]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    _ => vec![],
+                }
+            }
+        }
+
+        let mut egraph = crate::EGraph::<Math, ()>::default();
+        egraph.add_expr(&"(+ 1 (+ 2 x))".parse().unwrap());
+        let inner = egraph.add_expr(&"(+ 2 x)".parse().unwrap());
+        let two = egraph.add_expr(&"2".parse().unwrap());
+        let y = egraph.add_expr(&"y".parse().unwrap());
+        egraph.union(two, y);
+        egraph.rebuild();
+
+        let pattern = |s: &str| {
+            let pattern: Pattern<Math> = s.parse().unwrap();
+            assert_eq!(pattern.to_string(), s);
+            pattern
+        };
+        // the class of 2 and y has a leaf of each variant, the inner sum has none
+        assert_eq!(pattern("(+ ?a ?b)").n_matches(&egraph), 2);
+        assert_eq!(pattern("(+ ?a!Num ?b)").n_matches(&egraph), 2);
+        assert_eq!(pattern("(+ ?a!Symbol ?b)").n_matches(&egraph), 1);
+        assert_eq!(pattern("(+ ?a ?b!Num)").n_matches(&egraph), 0);
+        assert_eq!(pattern("(+ ?a ?b!Symbol)").n_matches(&egraph), 1);
+        assert_eq!(pattern("(+ ?a!Num ?a!Num)").n_matches(&egraph), 0);
+
+        let add = pattern("(+ ?a!Symbol ?b)");
+        assert_eq!(
+            add.program().to_string(),
+            "?a = r1, ?b = r2, ?a! = r3\n0: bind r0 (+ r1 r2)\n1: leaf r1 Symbol r3\n"
+        );
+        let matches = add.search_eclass(&egraph, inner).unwrap();
+        let a: Var = "?a".parse().unwrap();
+        let subst = &matches.substs[0];
+        assert_eq!(matches.substs.len(), 1);
+        assert_eq!(subst[a], egraph.find(two));
+        assert_eq!(subst.leaf::<Symbol, _, _>(&egraph, a), Some(&"y".into()));
+        assert_eq!(subst.leaf::<i32, _, _>(&egraph, a), None);
+
+        let multi: MultiPattern<Math> = "?s = (+ ?a!Num ?b), ?b = (+ ?c!Num ?d)".parse().unwrap();
+        assert_eq!(multi.n_matches(&egraph), 1);
+        assert!(matches!(
+            "(+ ?a! ?b)".parse::<Pattern<Math>>(),
+            Err(RecExprParseError::BadOp(
+                super::ENodeOrVarParseError::EmptyVariant(_)
+            ))
+        ));
+
+        let rules = vec![Rewrite::new("fold", pattern("(+ ?a!Num ?b!Num)"), Fold).unwrap()];
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 3))".parse().unwrap())
+            .run(&rules);
+        let six = runner.egraph.lookup(Math::Num(6)).unwrap();
+        assert_eq!(runner.egraph.find(runner.roots[0]), six);
+    }
+
+    #[test]
+    #[should_panic = "at most one sequence var"]
+    fn two_sequence_vars() {
+        let _: Pattern<S> = "(f ?xs... ?ys...)".parse().unwrap();
+    }
 }
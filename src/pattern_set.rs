@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+use std::iter::FromIterator;
+
+use crate::machine::ProgramTrie;
+use crate::*;
+
+/** Many [`Pattern`]s compiled together into one matching program.
+
+Searching each [`Pattern`] separately runs its own program on every
+e-class with its root operator.
+When many patterns start the same way, like `(+ ?a (+ ?b ?c))` and
+`(+ ?a 0)`, those programs start with the same instructions, and
+searching them one at a time repeats that work.
+A [`PatternSet`] merges the programs of patterns with the same root
+operator into a trie, so the instructions they share run once per e-class
+and only the parts where they differ run separately.
+
+[`SharedScheduler`] uses a [`PatternSet`] to search all of a
+[`Runner`]'s rules at once.
+
+```
+# use egg::*;
+let patterns: Vec<Pattern<SymbolLang>> = ["(+ ?a (+ ?b ?c))", "(+ ?a 0)", "(* ?a 1)"]
+    .iter()
+    .map(|p| p.parse().unwrap())
+    .collect();
+let set = PatternSet::new(patterns.clone());
+
+let mut egraph = EGraph::<SymbolLang, ()>::default();
+egraph.add_expr(&"(+ x (+ y 0))".parse().unwrap());
+egraph.rebuild();
+
+// one list of matches per pattern, the same as searching them one at a time
+let matches = set.search(&egraph);
+assert_eq!(matches.len(), 3);
+for (pattern, matches) in patterns.iter().zip(&matches) {
+    let n: usize = matches.iter().map(|m| m.substs.len()).sum();
+    assert_eq!(n, pattern.n_matches(&egraph));
+}
+```
+*/
+#[derive(Debug, Clone)]
+pub struct PatternSet<L: Language> {
+    patterns: Vec<Pattern<L>>,
+    /// A trie for each root operator, or `None` for the patterns that are
+    /// a bare variable.
+    tries: Vec<(Option<L::Discriminant>, ProgramTrie<L>)>,
+}
+
+impl<L: Language> PatternSet<L> {
+    /// Compiles `patterns` together.
+    pub fn new(patterns: impl IntoIterator<Item = Pattern<L>>) -> Self {
+        let patterns: Vec<Pattern<L>> = patterns.into_iter().collect();
+        let mut tries: Vec<(Option<L::Discriminant>, ProgramTrie<L>)> = vec![];
+        for (i, pattern) in patterns.iter().enumerate() {
+            let key = match pattern.ast.last().unwrap() {
+                ENodeOrVar::ENode(e) => Some(e.discriminant()),
+                ENodeOrVar::Var(_)
+                | ENodeOrVar::Guarded(..)
+                | ENodeOrVar::Seq(_)
+                | ENodeOrVar::Leaf(..) => None,
+            };
+            let trie = match tries.iter().position(|(k, _)| *k == key) {
+                Some(t) => &mut tries[t].1,
+                None => {
+                    tries.push((key, ProgramTrie::new()));
+                    &mut tries.last_mut().unwrap().1
+                }
+            };
+            trie.insert(i, &pattern.program);
+        }
+        Self { patterns, tries }
+    }
+
+    /// The patterns in this set, in the order they were given.
+    pub fn patterns(&self) -> &[Pattern<L>] {
+        &self.patterns
+    }
+
+    /// The number of instructions in the shared program.
+    /// This is at most the total number of instructions in the programs
+    /// of the patterns compiled separately.
+    pub fn n_instructions(&self) -> usize {
+        self.tries.iter().map(|(_, t)| t.n_instructions()).sum()
+    }
+
+    /// Search for all the patterns at once, returning the matches of each
+    /// pattern in the same order as [`patterns`](PatternSet::patterns()).
+    /// The matches of each pattern are the same ones
+    /// [`Searcher::search`] finds.
+    pub fn search<N: Analysis<L>>(&self, egraph: &EGraph<L, N>) -> Vec<Vec<SearchMatches<L>>> {
+        self.search_with_limit(egraph, usize::MAX)
+    }
+
+    /// Similar to [`search`](PatternSet::search()), but return at most
+    /// `limit` matches for each pattern.
+    pub fn search_with_limit<N: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, N>,
+        limit: usize,
+    ) -> Vec<Vec<SearchMatches<L>>> {
+        let mut matches: Vec<Vec<SearchMatches<L>>> =
+            self.patterns.iter().map(|_| vec![]).collect();
+        let mut counts = vec![0; self.patterns.len()];
+        for (key, trie) in &self.tries {
+            let ids: Vec<Id> = match key {
+                Some(key) => match egraph.classes_for_op(key) {
+                    Some(ids) => ids.collect(),
+                    None => continue,
+                },
+                None => egraph.classes().map(|c| c.id).collect(),
+            };
+            for eclass in ids {
+                if run::search_cancelled() {
+                    break;
+                }
+                trie.run(egraph, eclass, &mut |i, subst| {
+                    if counts[i] == limit {
+                        return;
+                    }
+                    counts[i] += 1;
+                    match matches[i].last_mut() {
+                        Some(m) if m.eclass == eclass => m.substs.push(subst),
+                        _ => matches[i].push(SearchMatches {
+                            eclass,
+                            substs: vec![subst],
+                            ast: Some(Cow::Borrowed(&self.patterns[i].ast)),
+                        }),
+                    }
+                });
+            }
+        }
+        matches
+    }
+}
+
+impl<L: Language> FromIterator<Pattern<L>> for PatternSet<L> {
+    fn from_iter<I: IntoIterator<Item = Pattern<L>>>(patterns: I) -> Self {
+        Self::new(patterns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    #[test]
+    fn shares_instructions() {
+        crate::init_logger();
+        let patterns: Vec<Pattern<S>> = [
+            "(+ ?a (+ ?b ?c))",
+            "(+ ?a (+ ?b ?b))",
+            "(+ ?a 0)",
+            "(+ (* ?a ?b) (* ?a ?c))",
+            "(* ?a 1)",
+            "(* ?a ?a)",
+            "?a",
+        ]
+        .iter()
+        .map(|p| p.parse().unwrap())
+        .collect();
+        let set: PatternSet<S> = patterns.iter().cloned().collect();
+        let separate: usize = patterns.iter().map(|p| p.program.n_instructions()).sum();
+        assert!(set.n_instructions() < separate);
+
+        let mut egraph = EGraph::<S, ()>::default();
+        egraph.add_expr(&"(+ (* x 1) (* x (+ y (+ z z))))".parse().unwrap());
+        egraph.add_expr(&"(+ (* x y) (* x x))".parse().unwrap());
+        egraph.add_expr(&"(+ 0 0)".parse().unwrap());
+        egraph.rebuild();
+
+        let flatten = |ms: &[SearchMatches<S>]| -> Vec<(Id, Subst)> {
+            ms.iter()
+                .flat_map(|m| m.substs.iter().map(move |s| (m.eclass, s.clone())))
+                .collect()
+        };
+        let shared = set.search(&egraph);
+        for (pattern, shared) in patterns.iter().zip(&shared) {
+            let separate = pattern.search(&egraph);
+            assert_eq!(
+                format!("{:?}", flatten(shared)),
+                format!("{:?}", flatten(&separate)),
+                "{}",
+                pattern
+            );
+        }
+
+        let limited = set.search_with_limit(&egraph, 1);
+        for ms in limited {
+            assert!(flatten(&ms).len() <= 1);
+        }
+    }
+}
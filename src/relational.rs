@@ -0,0 +1,549 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::*;
+
+/** A [`Searcher`] that matches a [`Pattern`] or [`MultiPattern`] with
+relational e-matching.
+
+Instead of backtracking over the e-graph one e-node at a time like the
+[`Pattern`] and [`MultiPattern`] searchers do, a [`RelationalPattern`]
+treats the pattern as a conjunctive query.
+Every e-node in the pattern becomes an atom over a table holding the
+e-nodes with that operator, one row `(eclass, child_1, .., child_n)` per
+e-node, and the query is answered with a worst-case optimal
+("generic") join that binds one variable at a time across all of the
+atoms that mention it.
+This is asymptotically faster on patterns where many variables are
+shared, like `(f ?x (g ?x ?y) (h ?y))`, and on multipatterns, which the
+backtracking searcher handles by scanning the whole e-graph.
+
+The tables are built from the e-graph each time you search, so this is
+best used with [`search`](Searcher::search()) rather than
+[`search_eclass`](Searcher::search_eclass()).
+It finds the same matches as the searcher it was made from, so you can
+choose it per rule.
+Since each table has a fixed number of columns, patterns with sequence
+variables (like `?xs...`) or leaf variables (like `?n!Num`) are not
+supported, and converting them panics.
+
+```
+# use egg::*;
+let lhs: Pattern<SymbolLang> = "(f ?x (g ?x ?y) (h ?y))".parse().unwrap();
+let rhs: Pattern<SymbolLang> = "(k ?x ?y)".parse().unwrap();
+let relational: Rewrite<SymbolLang, ()> =
+    Rewrite::new("relational", RelationalPattern::from(lhs.clone()), rhs.clone()).unwrap();
+let backtracking: Rewrite<SymbolLang, ()> = Rewrite::new("backtracking", lhs, rhs).unwrap();
+
+let mut egraph = EGraph::<SymbolLang, ()>::default();
+egraph.add_expr(&"(f a (g a b) (h b))".parse().unwrap());
+egraph.add_expr(&"(f a (g a b) (h c))".parse().unwrap());
+egraph.rebuild();
+
+let matches = relational.search(&egraph);
+assert_eq!(matches.len(), 1);
+assert_eq!(matches[0].eclass, backtracking.search(&egraph)[0].eclass);
+```
+*/
+#[derive(Debug, Clone)]
+pub struct RelationalPattern<L> {
+    atoms: Vec<Atom<L>>,
+    /// The query variables in the order the join binds them.
+    /// The first one is the root of the match.
+    order: Vec<usize>,
+    /// For each step of `order`, the atoms that mention that variable,
+    /// and which of their keys it is.
+    levels: Vec<Vec<(usize, usize)>>,
+    /// The query variable of each pattern variable.
+    bound: Vec<(Var, usize)>,
+    /// The guards on query variables, see [`EGraph::with_guard`].
+    guards: Vec<(usize, Symbol)>,
+    ast: Option<PatternAst<L>>,
+    vars: Vec<Var>,
+}
+
+/// One e-node of the pattern, as an atom in the query.
+#[derive(Debug, Clone)]
+struct Atom<L> {
+    /// The e-node with its children zeroed out.
+    node: L,
+    /// For each column of a row, the query variable it holds.
+    columns: Vec<usize>,
+    /// The distinct query variables of this atom, in join order.
+    keys: Vec<usize>,
+}
+
+/// Builds a query, merging query variables that must be equal.
+struct QueryBuilder<L> {
+    atoms: Vec<Atom<L>>,
+    parents: Vec<usize>,
+    vars: IndexMap<Var, usize>,
+    guards: Vec<(usize, Symbol)>,
+}
+
+impl<L: Language> QueryBuilder<L> {
+    fn new() -> Self {
+        Self {
+            atoms: vec![],
+            parents: vec![],
+            vars: Default::default(),
+            guards: vec![],
+        }
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.parents.push(self.parents.len());
+        self.parents.len() - 1
+    }
+
+    fn find(&self, mut q: usize) -> usize {
+        while self.parents[q] != q {
+            q = self.parents[q];
+        }
+        q
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[b] = a;
+    }
+
+    fn var(&mut self, v: Var) -> usize {
+        match self.vars.get(&v) {
+            Some(&q) => q,
+            None => {
+                let q = self.fresh();
+                self.vars.insert(v, q);
+                q
+            }
+        }
+    }
+
+    /// Adds an atom for each e-node of `pat`, returning the query
+    /// variable of its root.
+    fn add_pattern(&mut self, pat: &PatternAst<L>) -> usize {
+        let mut ids: Vec<usize> = Vec::with_capacity(pat.len());
+        for node in pat.as_ref() {
+            let q = match node {
+                ENodeOrVar::Var(v) => self.var(*v),
+                ENodeOrVar::Guarded(v, guard) => {
+                    let q = self.var(*v);
+                    self.guards.push((q, *guard));
+                    q
+                }
+                ENodeOrVar::Seq(v) => {
+                    panic!("RelationalPattern does not support sequence var {}", v)
+                }
+                ENodeOrVar::Leaf(v, _) => {
+                    panic!("RelationalPattern does not support leaf var {}", v)
+                }
+                ENodeOrVar::ENode(n) => {
+                    let q = self.fresh();
+                    let mut columns = vec![q];
+                    columns.extend(n.children().iter().map(|&c| ids[usize::from(c)]));
+                    self.atoms.push(Atom {
+                        node: n.clone().map_children(|_| Id::from(0)),
+                        columns,
+                        keys: vec![],
+                    });
+                    q
+                }
+            };
+            ids.push(q);
+        }
+        *ids.last().unwrap()
+    }
+
+    fn build(
+        mut self,
+        root: usize,
+        ast: Option<PatternAst<L>>,
+        vars: Vec<Var>,
+    ) -> RelationalPattern<L> {
+        // number the merged query variables densely
+        let mut numbering = IndexMap::<usize, usize>::default();
+        let mut number = |q: usize, this: &Self| {
+            let q = this.find(q);
+            let n = numbering.len();
+            *numbering.entry(q).or_insert(n)
+        };
+        let root = number(root, &self);
+        let mut atoms = std::mem::take(&mut self.atoms);
+        for atom in &mut atoms {
+            for q in &mut atom.columns {
+                *q = number(*q, &self);
+            }
+        }
+        let bound: Vec<(Var, usize)> = self
+            .vars
+            .iter()
+            .map(|(&v, &q)| (v, number(q, &self)))
+            .collect();
+        let guards: Vec<(usize, Symbol)> = std::mem::take(&mut self.guards)
+            .into_iter()
+            .map(|(q, guard)| (number(q, &self), guard))
+            .collect();
+        let n_vars = numbering.len();
+
+        // greedily bind the variable mentioned by the most atoms that
+        // already have a bound variable, then by the most atoms overall
+        let mut order = vec![root];
+        let mut is_bound = vec![false; n_vars];
+        is_bound[root] = true;
+        while order.len() < n_vars {
+            let score = |q: usize| {
+                let mentioning = atoms.iter().filter(|a| a.columns.contains(&q));
+                let connected = mentioning
+                    .clone()
+                    .filter(|a| a.columns.iter().any(|&c| is_bound[c]))
+                    .count();
+                (connected, mentioning.count())
+            };
+            let next = (0..n_vars)
+                .filter(|&q| !is_bound[q])
+                .max_by_key(|&q| (score(q), std::cmp::Reverse(q)))
+                .unwrap();
+            is_bound[next] = true;
+            order.push(next);
+        }
+
+        let position = |q: usize| order.iter().position(|&o| o == q).unwrap();
+        for atom in &mut atoms {
+            let mut keys = atom.columns.clone();
+            keys.sort_by_key(|&q| position(q));
+            keys.dedup();
+            atom.keys = keys;
+        }
+        let levels = order
+            .iter()
+            .map(|&q| {
+                atoms
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, atom)| Some((i, atom.keys.iter().position(|&k| k == q)?)))
+                    .collect()
+            })
+            .collect();
+
+        RelationalPattern {
+            atoms,
+            order,
+            levels,
+            bound,
+            guards,
+            ast,
+            vars,
+        }
+    }
+}
+
+impl<L: Language> From<Pattern<L>> for RelationalPattern<L> {
+    fn from(pattern: Pattern<L>) -> Self {
+        let mut builder = QueryBuilder::new();
+        let root = builder.add_pattern(&pattern.ast);
+        let vars = pattern.vars();
+        builder.build(root, Some(pattern.ast), vars)
+    }
+}
+
+impl<L: Language> From<MultiPattern<L>> for RelationalPattern<L> {
+    fn from(multipattern: MultiPattern<L>) -> Self {
+        assert!(!multipattern.asts.is_empty(), "empty multipattern");
+        let mut builder = QueryBuilder::new();
+        for (v, pat) in &multipattern.asts {
+            let q = builder.add_pattern(pat);
+            let binder = builder.var(*v);
+            builder.union(binder, q);
+        }
+        let root = builder.var(multipattern.asts[0].0);
+        let vars = <MultiPattern<L> as Searcher<L, ()>>::vars(&multipattern);
+        builder.build(root, None, vars)
+    }
+}
+
+impl<L: Language> RelationalPattern<L> {
+    /// Builds the table of each atom, with rows projected onto its keys
+    /// and sorted.
+    fn tables<N: Analysis<L>>(&self, egraph: &EGraph<L, N>) -> Vec<Vec<Vec<Id>>> {
+        self.atoms
+            .iter()
+            .map(|atom| {
+                let mut rows = vec![];
+                let ids = match egraph.classes_for_op(&atom.node.discriminant()) {
+                    Some(ids) => ids,
+                    None => return rows,
+                };
+                let mut row = vec![];
+                for id in ids {
+                    for node in egraph[id].iter().filter(|n| atom.node.matches(n)) {
+                        row.clear();
+                        row.resize(atom.keys.len(), None);
+                        let values = std::iter::once(id).chain(node.children().iter().copied());
+                        let consistent = atom.columns.iter().zip(values).all(|(q, id)| {
+                            let key = atom.keys.iter().position(|k| k == q).unwrap();
+                            *row[key].get_or_insert(id) == id
+                        });
+                        if consistent {
+                            rows.push(row.iter().map(|id| id.unwrap()).collect());
+                        }
+                    }
+                }
+                rows.sort_unstable();
+                rows.dedup();
+                rows
+            })
+            .collect()
+    }
+
+    fn search_with_root<N: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, N>,
+        root: Option<Id>,
+        limit: usize,
+    ) -> Vec<SearchMatches<L>> {
+        assert!(egraph.clean, "Tried to search a dirty e-graph!");
+        if limit == 0 {
+            return vec![];
+        }
+        let tables = self.tables(egraph);
+        let mut join = Join {
+            pattern: self,
+            egraph,
+            ranges: tables.iter().map(|rows| 0..rows.len()).collect(),
+            tables: &tables,
+            assignment: vec![Id::from(0); self.order.len()],
+            root,
+            limit,
+            matches: vec![],
+        };
+        join.run(0);
+        join.matches
+    }
+}
+
+/// The state of one generic join.
+struct Join<'a, 'p, L: Language, N: Analysis<L>> {
+    pattern: &'p RelationalPattern<L>,
+    egraph: &'a EGraph<L, N>,
+    tables: &'a [Vec<Vec<Id>>],
+    /// For each atom, the rows that agree with the variables bound so far.
+    ranges: Vec<Range<usize>>,
+    /// The value of each query variable bound so far.
+    assignment: Vec<Id>,
+    root: Option<Id>,
+    limit: usize,
+    matches: Vec<SearchMatches<'p, L>>,
+}
+
+impl<'a, 'p, L: Language, N: Analysis<L>> Join<'a, 'p, L, N> {
+    /// Binds the variables from `level` on, returning `false` once the
+    /// search should stop.
+    fn run(&mut self, level: usize) -> bool {
+        if level == self.pattern.order.len() {
+            return self.yield_match();
+        }
+
+        let pattern = self.pattern;
+        let q = pattern.order[level];
+        let atoms = &pattern.levels[level];
+        let candidates: Vec<Id> = if level == 0 && self.root.is_some() {
+            vec![self.root.unwrap()]
+        } else if let Some(&(smallest, key)) =
+            atoms.iter().min_by_key(|(a, _)| self.ranges[*a].len())
+        {
+            let rows = &self.tables[smallest][self.ranges[smallest].clone()];
+            let mut values: Vec<Id> = rows.iter().map(|row| row[key]).collect();
+            values.dedup();
+            values
+        } else {
+            // a variable no e-node mentions can be any e-class
+            self.egraph.classes().map(|c| c.id).collect()
+        };
+
+        let saved: Vec<Range<usize>> = atoms.iter().map(|(a, _)| self.ranges[*a].clone()).collect();
+        'values: for value in candidates {
+            // checking once per root e-class keeps the inner loops cheap
+            if level == 0 && run::search_cancelled() {
+                return false;
+            }
+            for &(_, guard) in pattern.guards.iter().filter(|(g, _)| *g == q) {
+                if !self.egraph.check_guard(guard, value) {
+                    continue 'values;
+                }
+            }
+            for (&(a, key), range) in atoms.iter().zip(&saved) {
+                let rows = &self.tables[a][range.clone()];
+                let start = rows.partition_point(|row| row[key] < value);
+                let end = rows.partition_point(|row| row[key] <= value);
+                if start == end {
+                    continue 'values;
+                }
+                self.ranges[a] = range.start + start..range.start + end;
+            }
+            self.assignment[q] = value;
+            if !self.run(level + 1) {
+                return false;
+            }
+        }
+        for (&(a, _), range) in atoms.iter().zip(saved) {
+            self.ranges[a] = range;
+        }
+        true
+    }
+
+    fn yield_match(&mut self) -> bool {
+        let root = self.assignment[self.pattern.order[0]];
+        if !self.egraph.analysis.allow_ematching_cycles()
+            && self.pattern.order[1..]
+                .iter()
+                .any(|&q| self.assignment[q] == root)
+        {
+            return true;
+        }
+
+        let mut subst = Subst::with_capacity(self.pattern.bound.len());
+        for &(v, q) in &self.pattern.bound {
+            subst.insert(v, self.assignment[q]);
+        }
+        match self.matches.last_mut() {
+            Some(m) if m.eclass == root => m.substs.push(subst),
+            _ => self.matches.push(SearchMatches {
+                eclass: root,
+                substs: vec![subst],
+                ast: self.pattern.ast.as_ref().map(Cow::Borrowed),
+            }),
+        }
+        self.limit -= 1;
+        self.limit > 0
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Searcher<L, N> for RelationalPattern<L> {
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<L>> {
+        let eclass = egraph.find(eclass);
+        self.search_with_root(egraph, Some(eclass), limit).pop()
+    }
+
+    fn search_with_limit(&self, egraph: &EGraph<L, N>, limit: usize) -> Vec<SearchMatches<L>> {
+        self.search_with_root(egraph, None, limit)
+    }
+
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        self.ast.as_ref()
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.vars.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    type EGraph = crate::EGraph<S, ()>;
+
+    /// The matches of `searcher`, sorted so searchers can be compared.
+    fn matches(egraph: &EGraph, searcher: &dyn Searcher<S, ()>) -> Vec<(Id, Vec<(Var, Id)>)> {
+        let mut matches: Vec<_> = searcher
+            .search(egraph)
+            .into_iter()
+            .flat_map(|m| {
+                let eclass = m.eclass;
+                m.substs.into_iter().map(move |s| {
+                    let mut subst = s.vec.to_vec();
+                    subst.sort();
+                    (eclass, subst)
+                })
+            })
+            .collect();
+        matches.sort();
+        matches
+    }
+
+    #[test]
+    fn same_as_backtracking() {
+        crate::init_logger();
+        let mut egraph = EGraph::default();
+        for expr in [
+            "(f a (g a b) (h b))",
+            "(f a (g a b) (h c))",
+            "(f b (g b b) (h b))",
+            "(f a a)",
+            "(f a b)",
+            "(f a c)",
+            "(g a a)",
+            "(g a b)",
+            "(+ 1 (+ 2 (+ 3 4)))",
+        ] {
+            egraph.add_expr(&expr.parse().unwrap());
+        }
+        let b = egraph.add_expr(&"b".parse().unwrap());
+        let c = egraph.add_expr(&"c".parse().unwrap());
+        egraph.union(b, c);
+        egraph.rebuild();
+
+        for pattern in [
+            "(f ?x (g ?x ?y) (h ?y))",
+            "(f ?x ?x)",
+            "(f ?x ?y)",
+            "(+ ?a (+ ?b ?c))",
+            "(f a ?x)",
+            "?x",
+        ] {
+            let pattern: Pattern<S> = pattern.parse().unwrap();
+            let relational = RelationalPattern::from(pattern.clone());
+            assert_eq!(
+                matches(&egraph, &relational),
+                matches(&egraph, &pattern),
+                "{}",
+                pattern
+            );
+        }
+
+        for multipattern in [
+            "?x = (f a a),   ?y = (f ?c b)",
+            "?x = (f ?a ?b), ?y = (f ?c ?d)",
+            "?x = (f ?a a),  ?y = (g ?a b)",
+            "?x = (f a b), ?x = (f a c)",
+            "?x = (f ?a ?b), ?y = (g ?b ?a)",
+        ] {
+            let multipattern: MultiPattern<S> = multipattern.parse().unwrap();
+            let relational = RelationalPattern::from(multipattern.clone());
+            assert_eq!(
+                matches(&egraph, &relational),
+                matches(&egraph, &multipattern),
+                "{:?}",
+                multipattern
+            );
+        }
+    }
+
+    #[test]
+    fn limits_and_eclasses() {
+        let mut egraph = EGraph::default();
+        let fab = egraph.add_expr(&"(f a b)".parse().unwrap());
+        egraph.add_expr(&"(f b a)".parse().unwrap());
+        egraph.add_expr(&"(f c c)".parse().unwrap());
+        egraph.rebuild();
+
+        let pattern: Pattern<S> = "(f ?x ?y)".parse().unwrap();
+        let relational = RelationalPattern::from(pattern);
+        assert_eq!(relational.n_matches(&egraph), 3);
+        let limited: usize = relational
+            .search_with_limit(&egraph, 2)
+            .iter()
+            .map(|m| m.substs.len())
+            .sum();
+        assert_eq!(limited, 2);
+
+        let m = relational.search_eclass(&egraph, fab).unwrap();
+        assert_eq!(m.eclass, fab);
+        assert_eq!(m.substs.len(), 1);
+    }
+}
@@ -70,6 +70,24 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
             }
         }
 
+        // a sequence var can only be used as a sequence var, and the other
+        // vars only as single vars
+        if let (Some(lhs), Some(rhs)) = (searcher.get_pattern_ast(), applier.get_pattern_ast()) {
+            for (v, is_seq) in pattern::var_kinds(rhs) {
+                if pattern::var_kinds(lhs).any(|(w, bound_seq)| w == v && bound_seq != is_seq) {
+                    let (bound, used) = if is_seq {
+                        ("a single var", "a sequence var")
+                    } else {
+                        ("a sequence var", "a single var")
+                    };
+                    return Err(format!(
+                        "Rewrite {} binds {} as {} but uses it as {}",
+                        name, v, bound, used
+                    ));
+                }
+            }
+        }
+
         Ok(Self {
             name,
             searcher,
@@ -91,6 +109,17 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
         self.searcher.search_with_limit(egraph, limit)
     }
 
+    /// Call [`search_touched`] on the [`Searcher`].
+    ///
+    /// [`search_touched`]: Searcher::search_touched()
+    pub fn search_touched(
+        &self,
+        egraph: &EGraph<L, N>,
+        generation: usize,
+    ) -> Vec<SearchMatches<L>> {
+        self.searcher.search_touched(egraph, generation)
+    }
+
     /// Call [`apply_matches`] on the [`Applier`].
     ///
     /// [`apply_matches`]: Applier::apply_matches()
@@ -137,7 +166,7 @@ where
 {
     let mut ms = vec![];
     for eclass in eclasses {
-        if limit == 0 {
+        if limit == 0 || run::search_cancelled() {
             break;
         }
         match searcher.search_eclass_with_limit(egraph, eclass, limit) {
@@ -198,6 +227,45 @@ where
         search_eclasses_with_limit(self, egraph, egraph.classes().map(|e| e.id), limit)
     }
 
+    /// Similar to [`search_with_limit`], but only searches the given
+    /// eclasses.
+    ///
+    /// The default implementation calls [`search_eclass_with_limit`] on
+    /// each eclass.
+    /// Searchers with some setup cost per search should override this so
+    /// they only pay it once.
+    ///
+    /// [`search_with_limit`]: Searcher::search_with_limit
+    /// [`search_eclass_with_limit`]: Searcher::search_eclass_with_limit
+    fn search_eclasses_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclasses: &[Id],
+        limit: usize,
+    ) -> Vec<SearchMatches<L>> {
+        search_eclasses_with_limit(self, egraph, eclasses.iter().copied(), limit)
+    }
+
+    /// Search for the matches that involve at least one eclass
+    /// touched in or after `generation`
+    /// (see [`EGraph::generation`]).
+    /// A match involves the eclasses of every subterm of the pattern,
+    /// including the eclasses bound to its variables.
+    ///
+    /// This is what makes semi-naive e-matching work: if you remember the
+    /// generation at which you last searched, searching again with that
+    /// generation finds only the matches that are new since then.
+    /// See [`IncrementalScheduler`].
+    ///
+    /// Implementations may return a superset of these matches, as long as
+    /// every match is one [`search`](Searcher::search()) would find.
+    /// The default implementation just calls
+    /// [`search`](Searcher::search()).
+    fn search_touched(&self, egraph: &EGraph<L, N>, generation: usize) -> Vec<SearchMatches<L>> {
+        let _ = generation;
+        self.search(egraph)
+    }
+
     /// Returns the number of matches in the e-graph
     fn n_matches(&self, egraph: &EGraph<L, N>) -> usize {
         self.search(egraph).iter().map(|m| m.substs.len()).sum()
@@ -212,6 +280,204 @@ where
     fn vars(&self) -> Vec<Var>;
 }
 
+/** A [`Searcher`] that only matches where another [`Searcher`] does not.
+
+An [`AbsentSearcher`] finds the matches of `searcher`, and then drops
+the ones where `absent` also matches with the same e-classes for the
+variables the two have in common.
+This is useful for rules that should only fire when some form does not
+exist yet, like normalizing only the terms that have no normal form.
+
+`absent` is searched once for each search, and its matches are
+joined with the matches of `searcher` in each e-class, which is much
+faster than searching for it inside a [`Condition`] for every match.
+[`search_eclass`](Searcher::search_eclass()) also searches for `absent`
+once, so to search many e-classes, use
+[`search_eclasses_with_limit`](Searcher::search_eclasses_with_limit())
+instead of searching them one at a time.
+If the two searchers share no variables, any match of `absent`
+anywhere in the e-graph rules out every match of `searcher`.
+
+Since the e-graph only grows, a match that was dropped because `absent`
+matched will stay dropped, but a match that was kept may be dropped by a
+later search once `absent` matches.
+
+```
+# use egg::*;
+// match `(f ?x)` where `?x` has no `g` e-node
+let searcher = AbsentSearcher {
+    searcher: "(f ?x)".parse::<Pattern<SymbolLang>>().unwrap(),
+    absent: "?x = (g ?y)".parse::<MultiPattern<SymbolLang>>().unwrap(),
+};
+
+let mut egraph = EGraph::<SymbolLang, ()>::default();
+egraph.add_expr(&"(f a)".parse().unwrap());
+egraph.add_expr(&"(f (g b))".parse().unwrap());
+egraph.rebuild();
+assert_eq!(searcher.n_matches(&egraph), 1);
+
+let rule: Rewrite<SymbolLang, ()> = rewrite!("wrap"; { searcher } => "(f (g ?x))");
+let runner = Runner::default().with_egraph(egraph).run(&[rule]);
+assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+```
+*/
+#[derive(Clone, Debug)]
+pub struct AbsentSearcher<S, A> {
+    /// The [`Searcher`] whose matches are returned.
+    pub searcher: S,
+    /// The [`Searcher`] that must not match.
+    pub absent: A,
+}
+
+/// The keys of the matches of [`AbsentSearcher::absent`], see [`shared_key`].
+struct Present {
+    shared: Vec<Var>,
+    keys: HashSet<Vec<Id>>,
+}
+
+impl<S, A> AbsentSearcher<S, A> {
+    fn present<L, N>(&self, egraph: &EGraph<L, N>) -> Present
+    where
+        L: Language,
+        N: Analysis<L>,
+        S: Searcher<L, N>,
+        A: Searcher<L, N>,
+    {
+        let searcher_vars = self.searcher.vars();
+        let mut shared = self.absent.vars();
+        shared.retain(|v| searcher_vars.contains(v));
+        let keys = self
+            .absent
+            .search(egraph)
+            .iter()
+            .flat_map(|m| &m.substs)
+            .map(|subst| shared_key(egraph, &shared, subst))
+            .collect();
+        Present { shared, keys }
+    }
+
+    /// Drops the matches of `present` from `matches`, keeping at most
+    /// `limit` of the rest.
+    fn retain_absent<L, N>(
+        egraph: &EGraph<L, N>,
+        present: &Present,
+        matches: &mut SearchMatches<L>,
+        limit: usize,
+    ) where
+        L: Language,
+        N: Analysis<L>,
+    {
+        matches.substs.retain(|subst| {
+            !present
+                .keys
+                .contains(&shared_key(egraph, &present.shared, subst))
+        });
+        matches.substs.truncate(limit);
+    }
+
+    /// Searches `eclasses` one at a time, checking each e-class's matches
+    /// against the same matches of `absent`.
+    fn search_absent<'a, L, N>(
+        &'a self,
+        egraph: &EGraph<L, N>,
+        eclasses: impl IntoIterator<Item = Id>,
+        mut limit: usize,
+    ) -> Vec<SearchMatches<'a, L>>
+    where
+        L: Language,
+        N: Analysis<L>,
+        S: Searcher<L, N>,
+        A: Searcher<L, N>,
+    {
+        let present = self.present(egraph);
+        let mut ms = vec![];
+        for eclass in eclasses {
+            if limit == 0 || run::search_cancelled() {
+                break;
+            }
+            if let Some(mut m) = self.searcher.search_eclass(egraph, eclass) {
+                Self::retain_absent(egraph, &present, &mut m, limit);
+                if !m.substs.is_empty() {
+                    limit -= m.substs.len();
+                    ms.push(m);
+                }
+            }
+        }
+        ms
+    }
+}
+
+/// The canonical e-classes `subst` binds the variables in `vars` to,
+/// with each sequence variable's e-classes after how many there are.
+fn shared_key<L: Language, N: Analysis<L>>(
+    egraph: &EGraph<L, N>,
+    vars: &[Var],
+    subst: &Subst,
+) -> Vec<Id> {
+    let mut key = vec![];
+    for &v in vars {
+        match subst.get_seq(v) {
+            Some(ids) => {
+                key.push(Id::from(ids.len()));
+                key.extend(ids.iter().map(|&id| egraph.find(id)));
+            }
+            None => key.push(egraph.find(subst[v])),
+        }
+    }
+    key
+}
+
+impl<S, A, L, N> Searcher<L, N> for AbsentSearcher<S, A>
+where
+    L: Language,
+    N: Analysis<L>,
+    S: Searcher<L, N>,
+    A: Searcher<L, N>,
+{
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<L>> {
+        self.search_absent(egraph, Some(eclass), limit).pop()
+    }
+
+    fn search_with_limit(&self, egraph: &EGraph<L, N>, limit: usize) -> Vec<SearchMatches<L>> {
+        self.search_absent(egraph, egraph.classes().map(|c| c.id), limit)
+    }
+
+    fn search_eclasses_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclasses: &[Id],
+        limit: usize,
+    ) -> Vec<SearchMatches<L>> {
+        self.search_absent(egraph, eclasses.iter().copied(), limit)
+    }
+
+    fn search_touched(&self, egraph: &EGraph<L, N>, generation: usize) -> Vec<SearchMatches<L>> {
+        let present = self.present(egraph);
+        let mut matches = self.searcher.search_touched(egraph, generation);
+        for m in &mut matches {
+            Self::retain_absent(egraph, &present, m, usize::MAX);
+        }
+        matches.retain(|m| !m.substs.is_empty());
+        matches
+    }
+
+    // Not forwarded to `searcher`, since anything that searched for the
+    // pattern directly would skip the check for `absent`.
+    // The matches still carry the pattern for explanations.
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        None
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.searcher.vars()
+    }
+}
+
 /// The righthand side of a [`Rewrite`].
 ///
 /// An [`Applier`] is anything that can do something with a
@@ -584,6 +850,28 @@ mod tests {
         assert_eq!(apps, vec![egraph.find(mul)]);
     }
 
+    #[test]
+    fn mismatched_seq_vars() {
+        let rewrite = |lhs: &str, rhs: &str| {
+            let lhs: Pattern<S> = lhs.parse().unwrap();
+            let rhs: Pattern<S> = rhs.parse().unwrap();
+            Rewrite::<S, ()>::new("rule", lhs, rhs)
+        };
+        let err = rewrite("(list ?xs...)", "(f ?xs)").unwrap_err();
+        assert!(err.contains("binds ?xs as a sequence var"), "{}", err);
+        let err = rewrite("(list ?x)", "(f ?x...)").unwrap_err();
+        assert!(err.contains("binds ?x as a single var"), "{}", err);
+        assert!(rewrite("(list ?x ?xs...)", "(f ?xs... ?x)").is_ok());
+
+        let lhs: Pattern<S> = "(list ?xs...)".parse().unwrap();
+        let rhs: Pattern<S> = "(f ?xs)".parse().unwrap();
+        let conditional = ConditionalApplier {
+            condition: |_: &mut EGraph, _, _: &Subst| true,
+            applier: rhs,
+        };
+        assert!(Rewrite::new("rule", lhs, conditional).is_err());
+    }
+
     #[test]
     fn fn_rewrite() {
         crate::init_logger();
@@ -648,4 +936,99 @@ mod tests {
         fold_add.run(&mut egraph);
         assert_eq!(egraph.equivs(&start, &goal), vec![egraph.find(root)]);
     }
+
+    #[test]
+    fn absent_searcher() {
+        crate::init_logger();
+        let mut egraph = EGraph::default();
+        egraph.add_expr(&"(f a)".parse().unwrap());
+        egraph.add_expr(&"(f b)".parse().unwrap());
+        egraph.add_expr(&"(f (g c))".parse().unwrap());
+        egraph.add_expr(&"(h b)".parse().unwrap());
+        egraph.rebuild();
+
+        let unless = |absent: &str| AbsentSearcher {
+            searcher: Pattern::<S>::from_str("(f ?x)").unwrap(),
+            absent: MultiPattern::<S>::from_str(absent).unwrap(),
+        };
+        assert_eq!(unless("?x = (g ?y)").n_matches(&egraph), 2);
+        assert_eq!(unless("?z = (h ?x)").n_matches(&egraph), 2);
+        assert_eq!(unless("?z = (h ?x), ?x = (g ?y)").n_matches(&egraph), 3);
+        // no shared variables, so `absent` matching anywhere rules out everything
+        assert_eq!(unless("?z = (h ?y)").n_matches(&egraph), 0);
+        assert_eq!(unless("?z = (k ?y)").n_matches(&egraph), 3);
+        assert_eq!(unless("?x = (g ?y)").search_with_limit(&egraph, 1).len(), 1);
+
+        let searcher = unless("?x = (g ?y)");
+        let b = egraph.lookup_expr(&"b".parse().unwrap()).unwrap();
+        let fb = egraph.lookup_expr(&"(f b)".parse().unwrap()).unwrap();
+        let fgc = egraph.lookup_expr(&"(f (g c))".parse().unwrap()).unwrap();
+        assert!(searcher.search_eclass(&egraph, fb).is_some());
+        assert!(searcher.search_eclass(&egraph, fgc).is_none());
+        let fa = egraph.lookup_expr(&"(f a)".parse().unwrap()).unwrap();
+        let found = searcher.search_eclasses_with_limit(&egraph, &[fa, fgc, fb], usize::MAX);
+        let found: Vec<Id> = found.iter().map(|m| m.eclass).collect();
+        assert_eq!(found, vec![fa, fb]);
+        assert_eq!(
+            searcher
+                .search_eclasses_with_limit(&egraph, &[fa, fb], 1)
+                .len(),
+            1
+        );
+
+        // once `b` has a `g` e-node, `(f b)` is no longer a match
+        let generation = egraph.generation();
+        let gd = egraph.add_expr(&"(g d)".parse().unwrap());
+        egraph.union(b, gd);
+        egraph.rebuild();
+        assert_eq!(searcher.n_matches(&egraph), 1);
+        assert!(searcher.search_touched(&egraph, generation).is_empty());
+    }
+
+    #[test]
+    fn absent_searched_once() {
+        use std::cell::Cell;
+
+        /// Counts the searches that are not limited to one e-class.
+        struct Counted(MultiPattern<S>, Cell<usize>);
+        impl Searcher<S, ()> for Counted {
+            fn search_eclass_with_limit(
+                &self,
+                egraph: &EGraph,
+                eclass: Id,
+                limit: usize,
+            ) -> Option<SearchMatches<S>> {
+                self.0.search_eclass_with_limit(egraph, eclass, limit)
+            }
+
+            fn search_with_limit(&self, egraph: &EGraph, limit: usize) -> Vec<SearchMatches<S>> {
+                self.1.set(self.1.get() + 1);
+                self.0.search_with_limit(egraph, limit)
+            }
+
+            fn vars(&self) -> Vec<Var> {
+                Searcher::<S, ()>::vars(&self.0)
+            }
+        }
+
+        crate::init_logger();
+        let mut egraph = EGraph::default();
+        for x in ["a", "b", "c", "(g d)"] {
+            egraph.add_expr(&format!("(f {})", x).parse().unwrap());
+        }
+        egraph.rebuild();
+
+        let searcher = AbsentSearcher {
+            searcher: Pattern::<S>::from_str("(f ?x)").unwrap(),
+            absent: Counted("?x = (g ?y)".parse().unwrap(), Cell::new(0)),
+        };
+        assert_eq!(searcher.n_matches(&egraph), 3);
+        assert_eq!(searcher.absent.1.get(), 1);
+        let ids: Vec<Id> = egraph.classes().map(|c| c.id).collect();
+        assert_eq!(
+            searcher.search_eclasses_with_limit(&egraph, &ids, 2).len(),
+            2
+        );
+        assert_eq!(searcher.absent.1.get(), 2);
+    }
 }
@@ -0,0 +1,170 @@
+use crate::unify::rename_apart;
+use crate::*;
+
+/** A static graph of which rules can enable which other rules.
+
+Rule `a` can enable rule `b` if an e-node that the right-hand side of
+`a` adds can be part of a match of the left-hand side of `b`, that is,
+if a subterm of `a`'s right-hand side that is not a variable unifies
+with such a subterm of `b`'s left-hand side (see [`PatternAst::unify`]).
+This helps to order rules into phases and to see why changing one rule
+makes saturation explode somewhere else.
+
+The graph over-approximates: a rule whose applier is not a pattern is
+assumed to enable every rule, a rule whose searcher is not a pattern is
+assumed to be enabled by every rule, and operators are only compared
+by their [`discriminant`](Language::discriminant()) when a pattern has
+sequence variables.
+Conditions are ignored.
+It does not track the merges that rules cause, so a rule whose
+right-hand side is a variable enables nothing.
+
+```
+use egg::{rewrite as rw, *};
+
+let rules: &[Rewrite<SymbolLang, ()>] = &[
+    rw!("mul-two"; "(* ?x 2)" => "(+ ?x ?x)"),
+    rw!("add-same"; "(+ ?x ?x)" => "(<< ?x 1)"),
+    rw!("shift-one"; "(<< ?x 1)" => "(* ?x 2)"),
+    rw!("add-comm"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+];
+let graph = RuleGraph::new(rules);
+assert_eq!(graph.edges, vec![(0, 1), (0, 3), (1, 2), (2, 0), (3, 1), (3, 3)]);
+let enabled: Vec<usize> = graph.enabled_by(1).collect();
+assert_eq!(enabled, vec![2]);
+
+assert_eq!(
+    graph.dot().to_string(),
+    r#"digraph rules {
+  0[label = "mul-two"]
+  1[label = "add-same"]
+  2[label = "shift-one"]
+  3[label = "add-comm"]
+  0 -> 1
+  0 -> 3
+  1 -> 2
+  2 -> 0
+  3 -> 1
+  3 -> 3
+}"#
+);
+```
+**/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleGraph {
+    /// The names of the rules, in the order they were given.
+    pub rules: Vec<Symbol>,
+    /// The pairs `(a, b)` of indices into `rules` where rule `a` can
+    /// enable rule `b`, sorted.
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl RuleGraph {
+    /// Computes the graph of the given rules.
+    pub fn new<'a, L, N>(rules: impl IntoIterator<Item = &'a Rewrite<L, N>>) -> Self
+    where
+        L: Language + 'a,
+        N: Analysis<L> + 'a,
+    {
+        let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
+        let mut edges = vec![];
+        for (a, rule_a) in rules.iter().enumerate() {
+            for (b, rule_b) in rules.iter().enumerate() {
+                let rhs = rule_a.applier.get_pattern_ast();
+                let lhs = rule_b.searcher.get_pattern_ast();
+                if can_enable(rhs, lhs) {
+                    edges.push((a, b));
+                }
+            }
+        }
+        RuleGraph {
+            rules: rules.iter().map(|rule| rule.name).collect(),
+            edges,
+        }
+    }
+
+    /// Iterates over the indices of the rules that rule `a` can enable.
+    pub fn enabled_by(&self, a: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |&&(from, _)| from == a)
+            .map(|&(_, to)| to)
+    }
+
+    /// Creates a [`RuleGraphDot`] to visualize this graph.
+    pub fn dot(&self) -> RuleGraphDot<'_> {
+        RuleGraphDot {
+            graph: self,
+            config: vec![],
+        }
+    }
+}
+
+fn can_enable<L: Language>(rhs: Option<&PatternAst<L>>, lhs: Option<&PatternAst<L>>) -> bool {
+    let (rhs, lhs) = match (rhs, lhs) {
+        (Some(rhs), Some(lhs)) => (rhs, lhs),
+        _ => return true,
+    };
+    let enodes = |pat: &PatternAst<L>| -> Vec<Id> {
+        pat.items()
+            .filter(|(_, node)| matches!(node, ENodeOrVar::ENode(_)))
+            .map(|(id, _)| id)
+            .collect()
+    };
+    let has_seqs = |pat: &PatternAst<L>| {
+        pat.as_ref()
+            .iter()
+            .any(|node| matches!(node, ENodeOrVar::Seq(_)))
+    };
+
+    if has_seqs(rhs) || has_seqs(lhs) {
+        let op = |pat: &PatternAst<L>, id: Id| match &pat[id] {
+            ENodeOrVar::ENode(node) => node.discriminant(),
+            _ => unreachable!(),
+        };
+        return enodes(rhs)
+            .into_iter()
+            .any(|i| enodes(lhs).into_iter().any(|j| op(rhs, i) == op(lhs, j)));
+    }
+
+    let lhs = rename_apart(&[lhs], &[rhs]).pop().unwrap();
+    let subterms: Vec<PatternAst<L>> = enodes(&lhs).into_iter().map(|j| lhs.extract(j)).collect();
+    enodes(rhs).into_iter().any(|i| {
+        let created = rhs.extract(i);
+        subterms.iter().any(|sub| created.unify(sub).is_some())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rewrite as rw, SymbolLang as S, *};
+
+    #[test]
+    fn rule_graph_fallbacks() {
+        let searcher = AbsentSearcher {
+            searcher: "(f ?x)".parse::<Pattern<S>>().unwrap(),
+            absent: "(g ?x)".parse::<Pattern<S>>().unwrap(),
+        };
+        let always = |_: &mut EGraph<S, ()>, _: Id, _: &Subst| true;
+        let rules: Vec<Rewrite<S, ()>> = vec![
+            rw!("absent"; { searcher } => "(h ?x)"),
+            rw!("conditional"; "(h ?x)" => "(k ?x)" if always),
+            rw!("list"; "(list ?x ?xs...)" => "(list ?xs...)"),
+            rw!("k"; "(k ?x)" => "(list ?x ?x)"),
+        ];
+        let graph = RuleGraph::new(&rules);
+        assert_eq!(
+            graph.edges,
+            vec![
+                (0, 0),
+                (0, 1),
+                (1, 0),
+                (1, 3),
+                (2, 0),
+                (2, 2),
+                (3, 0),
+                (3, 2)
+            ]
+        );
+    }
+}
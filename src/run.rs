@@ -1,4 +1,8 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use log::*;
 
@@ -55,6 +59,30 @@ Here are some of the things [`Runner`] does for you:
   If this limit is hit, it stops with
   [`StopReason::TimeLimit`].
 
+- Goals
+
+  You can give the runner goals with [`with_goal`](Runner::with_goal())
+  or [`with_pattern_goal`](Runner::with_pattern_goal()).
+  Once all of them hold, it stops with [`StopReason::GoalReached`].
+
+- Cancellation
+
+  You can hand the runner a [`CancelToken`] and cancel it from another
+  thread. The runner checks it between rules and during e-matching,
+  and stops with [`StopReason::Cancelled`].
+
+- Checkpointing
+
+  With the `serde-1` feature, you can save a runner's progress with
+  `Runner::save_checkpoint` and pick it up later with
+  `Runner::load_checkpoint`.
+
+- Phases
+
+  [`run_schedule`](Runner::run_schedule()) runs several named rule sets
+  against one [`EGraph`] according to a [`Schedule`], recording the
+  phase of each [`Iteration`].
+
 - Rule scheduling
 
   Some rules enable themselves, blowing up the [`EGraph`] and
@@ -63,6 +91,9 @@ Here are some of the things [`Runner`] does for you:
   govern when to run which rules.
 
   [`BackoffScheduler`] is the default scheduler.
+  [`IncrementalScheduler`] only searches for matches that are new
+  since the last iteration, and [`SharedScheduler`] searches many
+  rules at once with a [`PatternSet`].
 
 [`Runner`] generates [`Iteration`]s that record some data about
 each iteration.
@@ -145,14 +176,82 @@ pub struct Runner<L: Language, N: Analysis<L>, IterData = ()> {
     /// Why the `Runner` stopped. This will be `None` if it hasn't
     /// stopped yet.
     pub stop_reason: Option<StopReason>,
+    /// If the `Runner` stopped with [`StopReason::GoalReached`] and
+    /// explanations are enabled, the explanation of each goal, in the
+    /// order the goals were added.
+    pub goal_explanations: Vec<Explanation<L>>,
 
     /// The hooks added by the
     /// [`with_hook`](Runner::with_hook()) method, in insertion order.
     #[allow(clippy::type_complexity)]
     pub hooks: Vec<Box<dyn FnMut(&mut Self) -> Result<(), String>>>,
 
+    lifecycle_hooks: LifecycleHooks<L, N, IterData>,
+    #[allow(clippy::type_complexity)]
+    beam: Option<Box<dyn FnMut(&mut EGraph<L, N>) -> usize>>,
+    depth_limit: Option<usize>,
+    #[cfg(feature = "reports")]
+    event_log: Option<EventLog>,
     limits: RunnerLimits,
     scheduler: Box<dyn RewriteScheduler<L, N>>,
+    goals: Vec<Goal<L>>,
+}
+
+type Hook<R> = Box<dyn FnMut(&mut R) -> RunnerResult<()>>;
+type SearchHook<L, N, R> =
+    Box<dyn FnMut(&mut R, &[&Rewrite<L, N>], &mut [Vec<SearchMatches<L>>]) -> RunnerResult<()>>;
+type ApplyHook<R> = Box<dyn FnMut(&mut R, &IndexMap<Symbol, usize>) -> RunnerResult<()>>;
+type StopHook<R> = Box<dyn FnMut(&mut R, &StopReason)>;
+
+/// The hooks added by [`with_before_search_hook`](Runner::with_before_search_hook())
+/// and friends.
+struct LifecycleHooks<L: Language, N: Analysis<L>, IterData> {
+    before_search: Vec<Hook<Runner<L, N, IterData>>>,
+    after_search: Vec<SearchHook<L, N, Runner<L, N, IterData>>>,
+    after_apply: Vec<ApplyHook<Runner<L, N, IterData>>>,
+    after_rebuild: Vec<Hook<Runner<L, N, IterData>>>,
+    on_stop: Vec<StopHook<Runner<L, N, IterData>>>,
+}
+
+impl<L: Language, N: Analysis<L>, IterData> Default for LifecycleHooks<L, N, IterData> {
+    fn default() -> Self {
+        Self {
+            before_search: vec![],
+            after_search: vec![],
+            after_apply: vec![],
+            after_rebuild: vec![],
+            on_stop: vec![],
+        }
+    }
+}
+
+impl<L: Language, N: Analysis<L>, IterData> Debug for LifecycleHooks<L, N, IterData> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hooks = |n: usize| vec![format_args!("<dyn FnMut ..>"); n];
+        f.debug_struct("LifecycleHooks")
+            .field("before_search", &hooks(self.before_search.len()))
+            .field("after_search", &hooks(self.after_search.len()))
+            .field("after_apply", &hooks(self.after_apply.len()))
+            .field("after_rebuild", &hooks(self.after_rebuild.len()))
+            .field("on_stop", &hooks(self.on_stop.len()))
+            .finish()
+    }
+}
+
+/// A goal added by [`with_goal`](Runner::with_goal()) or
+/// [`with_pattern_goal`](Runner::with_pattern_goal()).
+#[derive(Debug, Clone)]
+enum Goal<L> {
+    Equivalent {
+        lhs: RecExpr<L>,
+        rhs: RecExpr<L>,
+        lhs_id: Id,
+        rhs_id: Id,
+    },
+    Matches {
+        root: Id,
+        pattern: Pattern<L>,
+    },
 }
 
 /// Describes the limits that would stop a [`Runner`].
@@ -162,6 +261,10 @@ pub struct RunnerLimits {
     node_limit: usize,
     time_limit: Duration,
     start_time: Option<Instant>,
+    /// The iteration and egraph size the runner was last resumed at.
+    resumed_at: (usize, usize),
+    cancel_token: Option<CancelToken>,
+    search_profile: SearchProfile,
 }
 
 impl RunnerLimits {
@@ -171,22 +274,51 @@ impl RunnerLimits {
         L: Language,
         N: Analysis<L>,
     {
+        if self
+            .cancel_token
+            .as_ref()
+            .map_or(false, |t| t.is_cancelled())
+        {
+            return Err(StopReason::Cancelled);
+        }
+
         let elapsed = self.start_time.unwrap().elapsed();
         if elapsed > self.time_limit {
             return Err(StopReason::TimeLimit(elapsed.as_secs_f64()));
         }
 
+        let (resumed_iteration, resumed_size) = self.resumed_at;
         let size = egraph.total_size();
-        if size > self.node_limit {
+        if size.saturating_sub(resumed_size) > self.node_limit {
             return Err(StopReason::NodeLimit(size));
         }
 
-        if iteration >= self.iter_limit {
+        if iteration.saturating_sub(resumed_iteration) >= self.iter_limit {
             return Err(StopReason::IterationLimit(iteration));
         }
 
         Ok(())
     }
+
+    /// Runs `search`, the search for the rewrite named `name`, adding the
+    /// time it takes to that rewrite's [`RuleProfile`].
+    /// While it runs, e-matching on this thread stops early once the
+    /// [`CancelToken`] of the [`Runner`] is cancelled.
+    ///
+    /// The default [`RewriteScheduler::search_rewrites`] and the schedulers
+    /// in `egg` search every rewrite through this, and custom
+    /// implementations should too.
+    pub fn timed_search<T>(&self, name: Symbol, search: impl FnOnce() -> T) -> T {
+        let _guard = self
+            .cancel_token
+            .as_ref()
+            .map(|t| t.check_while_searching());
+        let start = Instant::now();
+        let result = search();
+        self.search_profile
+            .record(name, start.elapsed().as_secs_f64());
+        result
+    }
 }
 
 impl<L, N> Default for Runner<L, N, ()>
@@ -212,9 +344,16 @@ where
             iterations,
             roots,
             stop_reason,
+            goal_explanations,
             hooks,
+            lifecycle_hooks,
+            beam,
+            depth_limit,
+            #[cfg(feature = "reports")]
+                event_log: _,
             limits,
             scheduler: _,
+            goals,
         } = self;
 
         f.debug_struct("Runner")
@@ -222,9 +361,17 @@ where
             .field("iterations", iterations)
             .field("roots", roots)
             .field("stop_reason", stop_reason)
+            .field(
+                "goal_explanations",
+                &vec![format_args!("<Explanation ..>"); goal_explanations.len()],
+            )
             .field("hooks", &vec![format_args!("<dyn FnMut ..>"); hooks.len()])
+            .field("lifecycle_hooks", lifecycle_hooks)
+            .field("beam", &beam.as_ref().map(|_| "<dyn FnMut ..>"))
+            .field("depth_limit", depth_limit)
             .field("limits", limits)
             .field("scheduler", &format_args!("<dyn RewriteScheduler ..>"))
+            .field("goals", goals)
             .finish()
     }
 }
@@ -232,7 +379,8 @@ where
 /// Error returned by [`Runner`] when it stops.
 ///
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde-1", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum StopReason {
     /// The egraph saturated, i.e., there was an iteration where we
     /// didn't learn anything new from applying the rules.
@@ -243,6 +391,15 @@ pub enum StopReason {
     NodeLimit(usize),
     /// The time limit was hit. The data is the time limit in seconds.
     TimeLimit(f64),
+    /// The [`CancelToken`] given to
+    /// [`with_cancel_token`](Runner::with_cancel_token()) was cancelled.
+    Cancelled,
+    /// All the goals given to [`with_goal`](Runner::with_goal()) or
+    /// [`with_pattern_goal`](Runner::with_pattern_goal()) hold.
+    GoalReached,
+    /// Every phase of the [`Schedule`] given to
+    /// [`run_schedule`](Runner::run_schedule()) finished.
+    ScheduleFinished,
     /// Some other reason to stop.
     Other(String),
 }
@@ -268,6 +425,8 @@ pub struct Report {
     pub search_time: f64,
     pub apply_time: f64,
     pub rebuild_time: f64,
+    /// The [`RuleProfile`] of each rule, summed across iterations.
+    pub rule_profiles: IndexMap<Symbol, RuleProfile>,
 }
 
 impl std::fmt::Display for Report {
@@ -283,19 +442,84 @@ impl std::fmt::Display for Report {
         writeln!(f, "    Search:  ({:.2}) {}", self.search_time / self.total_time, self.search_time)?;
         writeln!(f, "    Apply:   ({:.2}) {}", self.apply_time / self.total_time, self.apply_time)?;
         writeln!(f, "    Rebuild: ({:.2}) {}", self.rebuild_time / self.total_time, self.rebuild_time)?;
+
+        let mut top: Vec<_> = self.rule_profiles.iter().collect();
+        top.sort_by(|(_, a), (_, b)| b.total_time().total_cmp(&a.total_time()));
+        top.truncate(Report::TOP_RULES);
+        if !top.is_empty() {
+            writeln!(f, "  Top rules by time:")?;
+        }
+        for (name, p) in top {
+            writeln!(f, "    {}: search {:.4}, apply {:.4}, {} matches, {} applied, {} bans", name, p.search_time, p.apply_time, p.matches, p.applied, p.bans)?;
+        }
         Ok(())
     }
 }
 
+impl Report {
+    /// How many rules the [`Display`](std::fmt::Display) implementation
+    /// lists, most expensive first.
+    pub const TOP_RULES: usize = 5;
+}
+
+/// Profiling data about one rule, see [`Iteration::rule_profiles`] and
+/// [`Report::rule_profiles`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct RuleProfile {
+    /// Seconds spent searching for this rule.
+    pub search_time: f64,
+    /// Seconds spent applying this rule.
+    pub apply_time: f64,
+    /// The number of matches passed on to be applied.
+    pub matches: usize,
+    /// The number of times this rule was _newly_ applied.
+    pub applied: usize,
+    /// The number of times the [`RewriteScheduler`] banned this rule.
+    pub bans: usize,
+}
+
+impl RuleProfile {
+    /// Seconds spent searching for and applying this rule.
+    pub fn total_time(&self) -> f64 {
+        self.search_time + self.apply_time
+    }
+
+    fn add(&mut self, other: &RuleProfile) {
+        self.search_time += other.search_time;
+        self.apply_time += other.apply_time;
+        self.matches += other.matches;
+        self.applied += other.applied;
+        self.bans += other.bans;
+    }
+}
+
+/// Per-rule search times, shared with the searchers a [`Runner`] hands
+/// its [`RewriteScheduler`] so they can record into it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchProfile(Arc<Mutex<IndexMap<Symbol, f64>>>);
+
+impl SearchProfile {
+    pub(crate) fn record(&self, name: Symbol, seconds: f64) {
+        *self.0.lock().unwrap().entry(name).or_default() += seconds;
+    }
+
+    fn take(&self) -> IndexMap<Symbol, f64> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
 /// Data generated by running a [`Runner`] one iteration.
 ///
 /// If the `serde-1` feature is enabled, this implements
-/// [`serde::Serialize`][ser], which is useful if you want to output
-/// this as a JSON or some other format.
+/// [`serde::Serialize`][ser] and [`serde::Deserialize`][de], which is
+/// useful if you want to output this as a JSON or some other format.
 ///
 /// [ser]: https://docs.rs/serde/latest/serde/trait.Serialize.html
+/// [de]: https://docs.rs/serde/latest/serde/trait.Deserialize.html
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde-1", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct Iteration<IterData> {
     /// The number of enodes in the egraph at the start of this
@@ -307,6 +531,9 @@ pub struct Iteration<IterData> {
     /// A map from rule name to number of times it was _newly_ applied
     /// in this iteration.
     pub applied: IndexMap<Symbol, usize>,
+    /// A map from the name of each rule run in this iteration to how
+    /// much time it took and what it did.
+    pub rule_profiles: IndexMap<Symbol, RuleProfile>,
     /// Seconds spent running hooks.
     pub hook_time: f64,
     /// Seconds spent searching in this iteration.
@@ -322,8 +549,15 @@ pub struct Iteration<IterData> {
     pub data: IterData,
     /// The number of rebuild iterations done after this iteration completed.
     pub n_rebuilds: usize,
+    /// The number of e-nodes removed by
+    /// [`with_beam_pruning`](Runner::with_beam_pruning()) after this
+    /// iteration. The time this took is included in `rebuild_time`.
+    pub n_pruned: usize,
     /// If the runner stopped on this iterations, this is the reason
     pub stop_reason: Option<StopReason>,
+    /// The name of the rule set this iteration ran, if it was run as part
+    /// of a [`Schedule`].
+    pub phase: Option<Symbol>,
 }
 
 /// Type alias for the result of a [`Runner`].
@@ -343,13 +577,23 @@ where
                 node_limit: 10_000,
                 time_limit: Duration::from_secs(5),
                 start_time: None,
+                resumed_at: (0, 0),
+                cancel_token: None,
+                search_profile: SearchProfile::default(),
             },
             egraph: EGraph::new(analysis),
             roots: vec![],
             iterations: vec![],
             stop_reason: None,
+            goal_explanations: vec![],
             hooks: vec![],
+            lifecycle_hooks: Default::default(),
+            beam: None,
+            depth_limit: None,
+            #[cfg(feature = "reports")]
+            event_log: None,
             scheduler: Box::new(BackoffScheduler::default()),
+            goals: vec![],
         }
     }
 
@@ -371,6 +615,35 @@ where
         self
     }
 
+    /// Sets a [`CancelToken`] that can be used to stop this runner
+    /// from another thread.
+    ///
+    /// The token is checked between rules and inside e-matching, so
+    /// the runner stops promptly with [`StopReason::Cancelled`] even
+    /// in the middle of a long search.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    ///
+    /// let token = CancelToken::new();
+    /// // this would usually happen on another thread
+    /// token.cancel();
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ 5 2)".parse().unwrap())
+    ///     .with_cancel_token(token)
+    ///     .run(rules);
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::Cancelled)));
+    /// ```
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.limits.cancel_token = Some(token);
+        self
+    }
+
     /// Add a hook to instrument or modify the behavior of a [`Runner`].
     /// Each hook will run at the beginning of each iteration, i.e. before
     /// all the rewrites.
@@ -402,6 +675,247 @@ where
         self
     }
 
+    /// Add a hook to run before searching in each iteration, after the
+    /// ones added by [`with_hook`](Runner::with_hook()).
+    ///
+    /// Unlike [`with_hook`](Runner::with_hook()), returning an error
+    /// stops the runner with that [`StopReason`].
+    /// If your hook modifies the e-graph, make sure to call
+    /// [`rebuild`](EGraph::rebuild()).
+    pub fn with_before_search_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Self) -> RunnerResult<()> + 'static,
+    {
+        self.lifecycle_hooks.before_search.push(Box::new(hook));
+        self
+    }
+
+    /// Add a hook to run after searching in each iteration.
+    ///
+    /// The hook is given the rules of this iteration and, for each of them,
+    /// the matches that are about to be applied.
+    /// It may remove matches to keep them from being applied.
+    /// Returning an error stops the runner with that [`StopReason`]
+    /// before anything is applied.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a (+ b c))".parse().unwrap())
+    ///     .with_after_search_hook(|_runner, rules, matches| {
+    ///         for (rule, ms) in rules.iter().zip(matches.iter_mut()) {
+    ///             if rule.name.as_str() == "assoc-add" {
+    ///                 ms.clear();
+    ///             }
+    ///         }
+    ///         Ok(())
+    ///     })
+    ///     .run(rules);
+    /// assert!(runner.iterations.iter().all(|i| !i.applied.contains_key(&Symbol::from("assoc-add"))));
+    /// ```
+    pub fn with_after_search_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Self, &[&Rewrite<L, N>], &mut [Vec<SearchMatches<L>>]) -> RunnerResult<()>
+            + 'static,
+    {
+        self.lifecycle_hooks.after_search.push(Box::new(hook));
+        self
+    }
+
+    /// Add a hook to run after applying the rules in each iteration, before
+    /// the egraph is rebuilt.
+    ///
+    /// The hook is given how many times each rule was newly applied, like
+    /// [`Iteration::applied`].
+    /// Returning an error stops the runner with that [`StopReason`].
+    pub fn with_after_apply_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Self, &IndexMap<Symbol, usize>) -> RunnerResult<()> + 'static,
+    {
+        self.lifecycle_hooks.after_apply.push(Box::new(hook));
+        self
+    }
+
+    /// Add a hook to run after the egraph is rebuilt in each iteration.
+    ///
+    /// This is a good place to check custom goals or prune the egraph;
+    /// the runner rebuilds the egraph again afterwards if needed.
+    /// Returning an error stops the runner with that [`StopReason`].
+    pub fn with_after_rebuild_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Self) -> RunnerResult<()> + 'static,
+    {
+        self.lifecycle_hooks.after_rebuild.push(Box::new(hook));
+        self
+    }
+
+    /// Add a hook to run once when the runner stops, with the
+    /// [`StopReason`] it stopped for.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    ///
+    /// Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ 5 2)".parse().unwrap())
+    ///     .with_on_stop_hook(|runner, reason| {
+    ///         println!("Stopped after {} iterations: {:?}", runner.iterations.len(), reason);
+    ///     })
+    ///     .run(rules);
+    /// ```
+    pub fn with_on_stop_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Self, &StopReason) + 'static,
+    {
+        self.lifecycle_hooks.on_stop.push(Box::new(hook));
+        self
+    }
+
+    /// Keep the egraph within a beam of good programs by pruning it after
+    /// each iteration.
+    ///
+    /// After rebuilding, the runner finds the best cost of each e-class
+    /// with `cost_function`, as an [`Extractor`] would, and removes the
+    /// e-nodes whose cost is more than `factor` times the best cost of
+    /// their e-class.
+    /// The best e-node of each e-class is always kept, so extraction gives
+    /// the same result as without pruning.
+    ///
+    /// Pruned e-nodes are subsumed rather than forgotten: they stay in the
+    /// egraph's hashcons, so rewrites that build them again do not add them
+    /// back, and they still count towards the
+    /// [node limit](Runner::with_node_limit()).
+    /// They are never matched again, though, so they stop spawning more
+    /// hopeless alternatives.
+    /// The number of pruned e-nodes is recorded in
+    /// [`Iteration::n_pruned`].
+    ///
+    /// # Panics
+    /// If `factor` is less than 1.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    ///     rewrite!("expand"; "?a" => "(+ ?a 0)"),
+    /// ];
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a (+ b c))".parse().unwrap())
+    ///     .with_beam_pruning(AstSize, 1.5)
+    ///     .run(rules);
+    /// assert!(runner.iterations.iter().any(|i| i.n_pruned > 0));
+    /// ```
+    pub fn with_beam_pruning<CF>(mut self, cost_function: CF, factor: f64) -> Self
+    where
+        CF: CostFunction<L> + 'static,
+        CF::Cost: num_traits::ToPrimitive,
+    {
+        assert!(
+            factor >= 1.0,
+            "Beam factor must be at least 1, got {}",
+            factor
+        );
+        let mut cost_function = Some(cost_function);
+        self.beam = Some(Box::new(move |egraph| {
+            let (cf, pruned) = prune_to_beam(egraph, cost_function.take().unwrap(), factor);
+            cost_function = Some(cf);
+            pruned
+        }));
+        self
+    }
+
+    /// Only apply rules to e-classes within `depth` steps of the
+    /// [`roots`](Runner::roots).
+    ///
+    /// The roots are at depth 0, and the children of the e-nodes in an
+    /// e-class at depth `d` are at depth `d + 1`.
+    /// The set of e-classes in range is computed in each iteration after
+    /// searching, and matches rooted outside of it are dropped before they
+    /// are applied, so rewrites deep inside large or irrelevant subterms
+    /// do not grow the egraph.
+    /// Patterns may still match below the depth limit; only the e-class the
+    /// match is found in has to be in range.
+    ///
+    /// If the runner has no roots, this has no effect.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(f (+ a b) (g (+ c d)))".parse().unwrap())
+    ///     .with_depth_limit(1)
+    ///     .run(rules);
+    /// assert!(runner.egraph.lookup_expr(&"(+ b a)".parse().unwrap()).is_some());
+    /// assert!(runner.egraph.lookup_expr(&"(+ d c)".parse().unwrap()).is_none());
+    /// ```
+    pub fn with_depth_limit(mut self, depth: usize) -> Self {
+        self.depth_limit = Some(depth);
+        self
+    }
+
+    /// Stream a [JSON Lines](https://jsonlines.org/) trace of the run to
+    /// `writer`.
+    ///
+    /// Each line is a JSON object whose `"event"` field is one of:
+    /// - `"apply"`: a match about to be applied, with the `"iteration"`,
+    ///   the `"rule"` name, the e-`"class"` it was found in, and the
+    ///   `"subst"` as a map from variable names to e-class ids (lists of
+    ///   them for sequence variables, and the e-node id of the leaf for
+    ///   leaf variables, see [`EGraph::id_to_node`]).
+    /// - `"union"`: two e-classes were merged, with the ids they were
+    ///   merged `"from"` and `"to"`, the new `"root"` and the
+    ///   `"justification"` (a rule name or congruence).
+    /// - `"rebuild"`: the egraph was rebuilt after applying, with the
+    ///   `"iteration"`, `"n_unions"` and `"time"` in seconds.
+    /// - `"iteration"`: an iteration finished, with the same counts as
+    ///   the [`Iteration`], including the `"stop_reason"`.
+    ///
+    /// Events are written in the order they happen, so the events of an
+    /// iteration come right before its `"iteration"` line.
+    /// The writer is flushed after each iteration.
+    /// If writing fails, the runner stops with [`StopReason::Other`].
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    ///
+    /// let path = std::env::temp_dir().join("egg-event-log-example.jsonl");
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a b)".parse().unwrap())
+    ///     .with_event_log(std::fs::File::create(&path).unwrap())
+    ///     .run(rules);
+    ///
+    /// let log = std::fs::read_to_string(&path).unwrap();
+    /// let n_iterations = log.lines().filter(|l| l.contains(r#""event":"iteration""#)).count();
+    /// assert_eq!(n_iterations, runner.iterations.len());
+    /// assert!(log.starts_with(r#"{"event":"apply","iteration":0,"rule":"commute-add""#));
+    /// ```
+    #[cfg(feature = "reports")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "reports")))]
+    pub fn with_event_log(mut self, writer: impl std::io::Write + Send + 'static) -> Self {
+        self.event_log = Some(EventLog::new(writer));
+        self
+    }
+
     /// Change out the [`RewriteScheduler`] used by this [`Runner`].
     /// The default one is [`BackoffScheduler`].
     ///
@@ -421,9 +935,77 @@ where
         self
     }
 
+    /// Add a goal that holds once `lhs` and `rhs` are equivalent.
+    ///
+    /// Both expressions are added to the egraph (but not to
+    /// [`roots`](Runner::roots)).
+    /// After each iteration, the runner checks its goals and stops with
+    /// [`StopReason::GoalReached`] once all of them hold.
+    /// If explanations are enabled, the explanation of each goal is
+    /// then stored in [`goal_explanations`](Runner::goal_explanations).
+    ///
+    /// If the egraph is later replaced with
+    /// [`with_egraph`](Runner::with_egraph()), both expressions are added
+    /// to the new egraph.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_explanations_enabled()
+    ///     .with_goal(&"(+ a (+ b c))".parse().unwrap(), &"(+ (+ c b) a)".parse().unwrap())
+    ///     .run(rules);
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::GoalReached)));
+    /// assert_eq!(runner.goal_explanations.len(), 1);
+    /// ```
+    pub fn with_goal(mut self, lhs: &RecExpr<L>, rhs: &RecExpr<L>) -> Self {
+        let lhs_id = self.egraph.add_expr(lhs);
+        let rhs_id = self.egraph.add_expr(rhs);
+        self.goals.push(Goal::Equivalent {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+            lhs_id,
+            rhs_id,
+        });
+        self
+    }
+
+    /// Add a goal that holds once `pattern` matches the e-class of `root`.
+    ///
+    /// This is typically used with one of the [`roots`](Runner::roots).
+    /// Like the roots, `root` is not updated by
+    /// [`with_egraph`](Runner::with_egraph()), so it must be an e-class of
+    /// the egraph the runner is run with.
+    /// See [`with_goal`](Runner::with_goal()) for how goals are checked.
+    pub fn with_pattern_goal(mut self, root: Id, pattern: Pattern<L>) -> Self {
+        self.goals.push(Goal::Matches { root, pattern });
+        self
+    }
+
     /// Replace the [`EGraph`] of this `Runner`.
-    pub fn with_egraph(self, egraph: EGraph<L, N>) -> Self {
-        Self { egraph, ..self }
+    ///
+    /// The expressions of goals added with
+    /// [`with_goal`](Runner::with_goal()) are added to the new egraph.
+    pub fn with_egraph(mut self, egraph: EGraph<L, N>) -> Self {
+        self.egraph = egraph;
+        for goal in &mut self.goals {
+            if let Goal::Equivalent {
+                lhs,
+                rhs,
+                lhs_id,
+                rhs_id,
+            } = goal
+            {
+                *lhs_id = self.egraph.add_expr(lhs);
+                *rhs_id = self.egraph.add_expr(rhs);
+            }
+        }
+        self
     }
 
     /// Run this `Runner` until it stops.
@@ -437,55 +1019,233 @@ where
         N: 'a,
     {
         let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
-        check_rules(&rules);
-        self.egraph.rebuild();
-        loop {
-            let iter = self.run_one(&rules);
-            self.iterations.push(iter);
-            let stop_reason = self.iterations.last().unwrap().stop_reason.clone();
-            // we need to check_limits after the iteration is complete to check for iter_limit
-            if let Some(stop_reason) = stop_reason.or_else(|| self.check_limits().err()) {
-                info!("Stopping: {:?}", stop_reason);
-                self.stop_reason = Some(stop_reason);
-                break;
-            }
-        }
+        self.prepare(&rules);
+        while self.step_one(&rules).is_ok() {}
 
         assert!(!self.iterations.is_empty());
         assert!(self.stop_reason.is_some());
         self
     }
 
-    /// Enable explanations for this runner's egraph.
-    /// This allows the runner to explain why two expressions are
-    /// equivalent with the [`explain_equivalence`](Runner::explain_equivalence) function.
-    pub fn with_explanations_enabled(mut self) -> Self {
-        self.egraph = self.egraph.with_explanations_enabled();
-        self
-    }
+    /// Run this `Runner` according to a [`Schedule`] of named rule sets.
+    ///
+    /// Each leaf of the schedule runs one iteration with the rules of
+    /// the named set, and the name is recorded in that
+    /// [`Iteration`]'s [`phase`](Iteration::phase).
+    /// An iteration that saturates only ends its phase, not the run;
+    /// the runner keeps going until the whole schedule is done, in which
+    /// case it stops with [`StopReason::ScheduleFinished`], or until
+    /// some other [`StopReason`] (like a limit) applies.
+    /// Limits are checked across the whole run, not per phase.
+    ///
+    /// # Panics
+    /// If the schedule names a rule set that is not in `rule_sets`.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let expand: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("distribute"; "(* ?a (+ ?b ?c))" => "(+ (* ?a ?b) (* ?a ?c))"),
+    /// ];
+    /// let simplify: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("mul-1"; "(* ?a 1)" => "?a"),
+    /// ];
+    ///
+    /// let schedule = "(seq (saturate expand) (repeat 3 simplify))".parse().unwrap();
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(* x (+ 1 y))".parse().unwrap())
+    ///     .run_schedule(&[("expand", expand), ("simplify", simplify)], &schedule);
+    ///
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::ScheduleFinished)));
+    /// assert_eq!(runner.iterations[0].phase, Some("expand".into()));
+    /// assert_eq!(runner.iterations.last().unwrap().phase, Some("simplify".into()));
+    /// ```
+    pub fn run_schedule<R>(mut self, rule_sets: &[(&str, R)], schedule: &Schedule) -> Self
+    where
+        R: AsRef<[Rewrite<L, N>]>,
+    {
+        let rule_sets: IndexMap<Symbol, Vec<&Rewrite<L, N>>> = rule_sets
+            .iter()
+            .map(|(name, rules)| {
+                let rules: Vec<&Rewrite<L, N>> = rules.as_ref().iter().collect();
+                check_rules(&rules);
+                (Symbol::from(*name), rules)
+            })
+            .collect();
+        schedule.for_each_rule_set(&mut |name| {
+            assert!(
+                rule_sets.contains_key(&name),
+                "Schedule uses unknown rule set {}",
+                name
+            )
+        });
 
-    /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
-    /// Use this function to turn this algorithm off.
-    pub fn without_explanation_length_optimization(mut self) -> Self {
-        self.egraph = self.egraph.without_explanation_length_optimization();
-        self
-    }
+        self.egraph.rebuild();
+        if self.run_phase(&rule_sets, schedule).is_ok() {
+            self.stop(StopReason::ScheduleFinished);
+        }
 
-    /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
-    /// Use this function to turn this algorithm on again if you have turned it off.
-    pub fn with_explanation_length_optimization(mut self) -> Self {
-        self.egraph = self.egraph.with_explanation_length_optimization();
+        assert!(self.stop_reason.is_some());
         self
     }
 
-    /// Disable explanations for this runner's egraph.
-    pub fn with_explanations_disabled(mut self) -> Self {
-        self.egraph = self.egraph.with_explanations_disabled();
-        self
+    /// Runs `schedule`, returning whether it changed the egraph.
+    fn run_phase(
+        &mut self,
+        rule_sets: &IndexMap<Symbol, Vec<&Rewrite<L, N>>>,
+        schedule: &Schedule,
+    ) -> RunnerResult<bool>
+where {
+        match schedule {
+            Schedule::Run(name) => {
+                info!("Phase {}", name);
+                let result = self.iterate(&rule_sets[name], Some(*name));
+                let result = match result {
+                    Err(StopReason::Saturated) => {
+                        // saturating only ends this phase
+                        self.iterations.last_mut().unwrap().stop_reason = None;
+                        self.check_limits().map(|()| false)
+                    }
+                    result => result.map(|()| true),
+                };
+                result.map_err(|stop_reason| self.stop(stop_reason))
+            }
+            Schedule::Seq(schedules) => {
+                let mut changed = false;
+                for schedule in schedules {
+                    changed |= self.run_phase(rule_sets, schedule)?;
+                }
+                Ok(changed)
+            }
+            Schedule::Repeat(n, schedule) => {
+                let mut changed = false;
+                for _ in 0..*n {
+                    if !self.run_phase(rule_sets, schedule)? {
+                        break;
+                    }
+                    changed = true;
+                }
+                Ok(changed)
+            }
+            Schedule::Saturate(schedule) => {
+                let mut changed = false;
+                while self.run_phase(rule_sets, schedule)? {
+                    changed = true;
+                }
+                Ok(changed)
+            }
+        }
     }
 
-    /// Calls [`EGraph::explain_equivalence`](EGraph::explain_equivalence()).
-    pub fn explain_equivalence(&mut self, left: &RecExpr<L>, right: &RecExpr<L>) -> Explanation<L> {
+    /// Run a single iteration of this `Runner`.
+    ///
+    /// This returns `Err` with the [`StopReason`] if the runner stopped
+    /// during this iteration, in which case
+    /// [`stop_reason`](Runner::stop_reason) is also set.
+    /// Calling this on a runner that has already stopped does nothing and
+    /// returns the existing stop reason; see [`resume`](Runner::resume())
+    /// to continue it.
+    ///
+    /// Unlike [`run`](Runner::run()), this does not consume the runner,
+    /// so you can inspect (or even modify) the egraph between steps.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    ///
+    /// let mut runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap());
+    /// for _ in 0..2 {
+    ///     runner.step(rules).unwrap();
+    ///     println!("Egraph is this big: {}", runner.egraph.total_size());
+    /// }
+    /// assert_eq!(runner.iterations.len(), 2);
+    /// ```
+    pub fn step<'a, R>(&mut self, rules: R) -> RunnerResult<()>
+    where
+        R: IntoIterator<Item = &'a Rewrite<L, N>>,
+        L: 'a,
+        N: 'a,
+    {
+        if let Some(stop_reason) = &self.stop_reason {
+            return Err(stop_reason.clone());
+        }
+
+        let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
+        self.prepare(&rules);
+        self.step_one(&rules)
+    }
+
+    /// Clear the [`stop_reason`](Runner::stop_reason) so that a stopped
+    /// `Runner` can continue with [`run`](Runner::run()) or
+    /// [`step`](Runner::step()).
+    ///
+    /// The limits start counting again from here: the resumed runner may
+    /// run as many more iterations as the iteration limit, add as many
+    /// more e-nodes as the node limit, and take as much more time as the
+    /// time limit.
+    /// Any limit can be changed before continuing, e.g. with
+    /// [`with_iter_limit`](Runner::with_iter_limit()).
+    /// The [`iterations`](Runner::iterations) from before are kept.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    ///
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+    ///     .with_iter_limit(1)
+    ///     .run(rules);
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::IterationLimit(1))));
+    ///
+    /// let runner = runner.resume().with_iter_limit(2).run(rules);
+    /// assert_eq!(runner.iterations.len(), 3);
+    /// ```
+    pub fn resume(mut self) -> Self {
+        self.stop_reason = None;
+        self.limits.start_time = None;
+        self.limits.resumed_at = (self.iterations.len(), self.egraph.total_size());
+        self
+    }
+
+    /// Enable explanations for this runner's egraph.
+    /// This allows the runner to explain why two expressions are
+    /// equivalent with the [`explain_equivalence`](Runner::explain_equivalence) function.
+    pub fn with_explanations_enabled(mut self) -> Self {
+        self.egraph = self.egraph.with_explanations_enabled();
+        self
+    }
+
+    /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
+    /// Use this function to turn this algorithm off.
+    pub fn without_explanation_length_optimization(mut self) -> Self {
+        self.egraph = self.egraph.without_explanation_length_optimization();
+        self
+    }
+
+    /// By default, egg runs a greedy algorithm to reduce the size of resulting explanations (without complexity overhead).
+    /// Use this function to turn this algorithm on again if you have turned it off.
+    pub fn with_explanation_length_optimization(mut self) -> Self {
+        self.egraph = self.egraph.with_explanation_length_optimization();
+        self
+    }
+
+    /// Disable explanations for this runner's egraph.
+    pub fn with_explanations_disabled(mut self) -> Self {
+        self.egraph = self.egraph.with_explanations_disabled();
+        self
+    }
+
+    /// Calls [`EGraph::explain_equivalence`](EGraph::explain_equivalence()).
+    pub fn explain_equivalence(&mut self, left: &RecExpr<L>, right: &RecExpr<L>) -> Explanation<L> {
         self.egraph.explain_equivalence(left, right)
     }
 
@@ -506,7 +1266,14 @@ where
 
     /// Creates a [`Report`] summarizing this `Runner`s run.
     pub fn report(&self) -> Report {
+        let mut rule_profiles: IndexMap<Symbol, RuleProfile> = IndexMap::default();
+        for iteration in &self.iterations {
+            for (name, profile) in &iteration.rule_profiles {
+                rule_profiles.entry(*name).or_default().add(profile);
+            }
+        }
         Report {
+            rule_profiles,
             stop_reason: self.stop_reason.clone().unwrap(),
             iterations: self.iterations.len(),
             egraph_nodes: self.egraph.total_number_of_nodes(),
@@ -520,7 +1287,8 @@ where
         }
     }
 
-    fn run_one(&mut self, rules: &[&Rewrite<L, N>]) -> Iteration<IterData> {
+    fn run_one(&mut self, rules: &[&Rewrite<L, N>], phase: Option<Symbol>) -> Iteration<IterData>
+where {
         assert!(self.stop_reason.is_none());
 
         info!("\nIteration {}", self.iterations.len());
@@ -531,6 +1299,9 @@ where
         let egraph_nodes = self.egraph.total_size();
         let egraph_classes = self.egraph.number_of_classes();
 
+        #[cfg(feature = "reports")]
+        self.egraph.event_log.clone_from(&self.event_log);
+
         let hook_time = Instant::now();
         let mut hooks = std::mem::take(&mut self.hooks);
         result = result.and_then(|_| {
@@ -539,7 +1310,10 @@ where
                 .try_for_each(|hook| hook(self).map_err(StopReason::Other))
         });
         self.hooks = hooks;
-        let hook_time = hook_time.elapsed().as_secs_f64();
+        let mut hooks = std::mem::take(&mut self.lifecycle_hooks.before_search);
+        result = result.and_then(|_| hooks.iter_mut().try_for_each(|hook| hook(self)));
+        self.lifecycle_hooks.before_search = hooks;
+        let mut hook_time = hook_time.elapsed().as_secs_f64();
 
         let egraph_nodes_after_hooks = self.egraph.total_size();
         let egraph_classes_after_hooks = self.egraph.number_of_classes();
@@ -549,12 +1323,27 @@ where
 
         let start_time = Instant::now();
 
+        let search_profile = self.limits.search_profile.clone();
+
         let mut matches = Vec::new();
         let mut applied = IndexMap::default();
+        let mut rule_profiles: IndexMap<Symbol, RuleProfile> = rules
+            .iter()
+            .map(|rw| (rw.name, RuleProfile::default()))
+            .collect();
         result = result.and_then(|_| {
             matches = self
                 .scheduler
                 .search_rewrites(i, &self.egraph, rules, &self.limits)?;
+            match self.depth_limit {
+                Some(depth) if !self.roots.is_empty() => {
+                    let scope: HashSet<Id> = self.classes_within(depth).into_iter().collect();
+                    for ms in &mut matches {
+                        ms.retain(|m| scope.contains(&m.eclass));
+                    }
+                }
+                _ => (),
+            }
             Ok(())
             // rules.iter().try_for_each(|rw| {
             //     let ms = self.scheduler.search_rewrite(i, &self.egraph, rw);
@@ -566,6 +1355,22 @@ where
         let search_time = start_time.elapsed().as_secs_f64();
         info!("Search time: {}", search_time);
 
+        let search_hook_time = Instant::now();
+        let mut hooks = std::mem::take(&mut self.lifecycle_hooks.after_search);
+        result = result.and_then(|_| {
+            hooks
+                .iter_mut()
+                .try_for_each(|hook| hook(self, rules, &mut matches))
+        });
+        self.lifecycle_hooks.after_search = hooks;
+        hook_time += search_hook_time.elapsed().as_secs_f64();
+        for (name, time) in search_profile.take() {
+            rule_profiles.entry(name).or_default().search_time += time;
+        }
+        for name in self.scheduler.take_bans() {
+            rule_profiles.entry(name).or_default().bans += 1;
+        }
+
         let apply_time = Instant::now();
 
         result = result.and_then(|_| {
@@ -573,7 +1378,26 @@ where
                 let total_matches: usize = ms.iter().map(|m| m.substs.len()).sum();
                 debug!("Applying {} {} times", rw.name, total_matches);
 
+                #[cfg(feature = "reports")]
+                if let Some(event_log) = &self.event_log {
+                    for m in &ms {
+                        for subst in &m.substs {
+                            event_log.log(&Event::Apply {
+                                iteration: i,
+                                rule: rw.name,
+                                class: m.eclass,
+                                subst: SubstJson(subst),
+                            });
+                        }
+                    }
+                }
+
+                let rule_apply_time = Instant::now();
                 let actually_matched = self.scheduler.apply_rewrite(i, &mut self.egraph, rw, ms);
+                let profile = &mut rule_profiles[&rw.name];
+                profile.apply_time += rule_apply_time.elapsed().as_secs_f64();
+                profile.matches += total_matches;
+                profile.applied += actually_matched;
                 if actually_matched > 0 {
                     if let Some(count) = applied.get_mut(&rw.name) {
                         *count += actually_matched;
@@ -589,14 +1413,49 @@ where
         let apply_time = apply_time.elapsed().as_secs_f64();
         info!("Apply time: {}", apply_time);
 
+        let apply_hook_time = Instant::now();
+        let mut hooks = std::mem::take(&mut self.lifecycle_hooks.after_apply);
+        result = result.and_then(|_| hooks.iter_mut().try_for_each(|hook| hook(self, &applied)));
+        self.lifecycle_hooks.after_apply = hooks;
+        hook_time += apply_hook_time.elapsed().as_secs_f64();
+
         let rebuild_time = Instant::now();
-        let n_rebuilds = self.egraph.rebuild();
+        let mut n_rebuilds = self.egraph.rebuild();
         if self.egraph.are_explanations_enabled() {
             debug_assert!(self.egraph.check_each_explain(rules));
         }
+        let mut n_pruned = 0;
+        if let Some(beam) = &mut self.beam {
+            n_pruned = beam(&mut self.egraph);
+            if n_pruned > 0 {
+                // refresh the index of classes by operator
+                self.egraph.rebuild();
+            }
+            info!("Pruned {} nodes", n_pruned);
+        }
+        let mut rebuild_time = rebuild_time.elapsed().as_secs_f64();
+
+        let rebuild_hook_time = Instant::now();
+        let mut hooks = std::mem::take(&mut self.lifecycle_hooks.after_rebuild);
+        result = result.and_then(|_| hooks.iter_mut().try_for_each(|hook| hook(self)));
+        self.lifecycle_hooks.after_rebuild = hooks;
+        hook_time += rebuild_hook_time.elapsed().as_secs_f64();
+        if !self.egraph.clean {
+            let start = Instant::now();
+            n_rebuilds += self.egraph.rebuild();
+            rebuild_time += start.elapsed().as_secs_f64();
+        }
 
-        let rebuild_time = rebuild_time.elapsed().as_secs_f64();
         info!("Rebuild time: {}", rebuild_time);
+
+        #[cfg(feature = "reports")]
+        if let Some(event_log) = &self.event_log {
+            event_log.log(&Event::Rebuild {
+                iteration: i,
+                n_unions: n_rebuilds,
+                time: rebuild_time,
+            });
+        }
         info!(
             "Size: n={}, e={}",
             self.egraph.total_size(),
@@ -613,12 +1472,21 @@ where
             && (egraph_nodes == self.egraph.total_size())
             && (egraph_classes == self.egraph.number_of_classes());
 
+        if self.goals_reached() {
+            result = result.and(Err(StopReason::GoalReached));
+            if self.egraph.are_explanations_enabled() {
+                self.goal_explanations = self.explain_goals();
+            }
+        }
+
         if can_be_saturated {
             result = result.and(Err(StopReason::Saturated))
         }
 
-        Iteration {
+        #[allow(unused_mut)]
+        let mut iteration = Iteration {
             applied,
+            rule_profiles,
             egraph_nodes,
             egraph_classes,
             hook_time,
@@ -626,10 +1494,136 @@ where
             apply_time,
             rebuild_time,
             n_rebuilds,
+            n_pruned,
             data: IterData::make(self),
             total_time: start_time.elapsed().as_secs_f64(),
             stop_reason: result.err(),
+            phase,
+        };
+
+        #[cfg(feature = "reports")]
+        self.egraph.event_log.take();
+
+        #[cfg(feature = "reports")]
+        if let Some(event_log) = &self.event_log {
+            event_log.log(&Event::Iteration {
+                iteration: i,
+                egraph_nodes: iteration.egraph_nodes,
+                egraph_classes: iteration.egraph_classes,
+                applied: &iteration.applied,
+                stop_reason: &iteration.stop_reason,
+                phase: iteration.phase,
+            });
+            if let Err(err) = event_log.flush() {
+                let stop_reason = StopReason::Other(format!("Failed to write event log: {}", err));
+                iteration.stop_reason.get_or_insert(stop_reason);
+            }
+        }
+
+        iteration
+    }
+
+    fn step_one(&mut self, rules: &[&Rewrite<L, N>]) -> RunnerResult<()>
+where {
+        self.iterate(rules, None)
+            .map_err(|stop_reason| self.stop(stop_reason))
+    }
+
+    /// Runs and records one iteration, returning why the runner should
+    /// stop without actually stopping it.
+    fn iterate(&mut self, rules: &[&Rewrite<L, N>], phase: Option<Symbol>) -> RunnerResult<()>
+where {
+        let iter = self.run_one(rules, phase);
+        self.iterations.push(iter);
+        let stop_reason = self.iterations.last().unwrap().stop_reason.clone();
+        // we need to check_limits after the iteration is complete to check for iter_limit
+        match stop_reason {
+            Some(stop_reason) => Err(stop_reason),
+            None => self.check_limits(),
+        }
+    }
+
+    /// Stops the runner, running the on-stop hooks.
+    fn stop(&mut self, stop_reason: StopReason) -> StopReason {
+        info!("Stopping: {:?}", stop_reason);
+        self.stop_reason = Some(stop_reason.clone());
+        let mut hooks = std::mem::take(&mut self.lifecycle_hooks.on_stop);
+        for hook in &mut hooks {
+            hook(self, &stop_reason);
+        }
+        self.lifecycle_hooks.on_stop = hooks;
+        stop_reason
+    }
+
+    /// The e-classes within `depth` of the roots, in breadth-first order.
+    fn classes_within(&self, depth: usize) -> Vec<Id> {
+        let egraph = &self.egraph;
+        let mut seen = HashSet::default();
+        let mut scope: Vec<Id> = vec![];
+        for &root in &self.roots {
+            let root = egraph.find(root);
+            if seen.insert(root) {
+                scope.push(root);
+            }
+        }
+
+        let mut frontier = 0..scope.len();
+        for _ in 0..depth {
+            let start = scope.len();
+            for i in frontier {
+                for node in egraph[scope[i]].iter() {
+                    for &child in node.children() {
+                        let child = egraph.find(child);
+                        if seen.insert(child) {
+                            scope.push(child);
+                        }
+                    }
+                }
+            }
+            frontier = start..scope.len();
         }
+        debug!(
+            "{} of {} classes in scope",
+            scope.len(),
+            egraph.number_of_classes()
+        );
+        scope
+    }
+
+    fn goals_reached(&self) -> bool {
+        let egraph = &self.egraph;
+        !self.goals.is_empty()
+            && self.goals.iter().all(|goal| match goal {
+                Goal::Equivalent { lhs_id, rhs_id, .. } => {
+                    egraph.find(*lhs_id) == egraph.find(*rhs_id)
+                }
+                Goal::Matches { root, pattern } => {
+                    pattern.search_eclass(egraph, egraph.find(*root)).is_some()
+                }
+            })
+    }
+
+    fn explain_goals(&mut self) -> Vec<Explanation<L>> {
+        let egraph = &mut self.egraph;
+        self.goals
+            .iter()
+            .map(|goal| match goal {
+                Goal::Equivalent { lhs, rhs, .. } => egraph.explain_equivalence(lhs, rhs),
+                Goal::Matches { root, pattern } => {
+                    let root = egraph.find(*root);
+                    let matches = pattern.search_eclass(egraph, root).unwrap();
+                    let expr = egraph.id_to_expr(root);
+                    egraph.explain_matches(&expr, &pattern.ast, &matches.substs[0])
+                }
+            })
+            .collect()
+    }
+
+    /// The setup [`run`](Runner::run()) and [`step`](Runner::step()) do
+    /// before running any iterations.
+    fn prepare(&mut self, rules: &[&Rewrite<L, N>]) {
+        check_rules(rules);
+        self.egraph.rebuild();
     }
 
     fn try_start(&mut self) {
@@ -642,6 +1636,122 @@ where
     }
 }
 
+#[cfg(feature = "serde-1")]
+#[derive(serde::Serialize)]
+#[serde(bound = "EGraph<L, N>: serde::Serialize, IterData: serde::Serialize")]
+struct CheckpointRef<'a, L: Language, N: Analysis<L>, IterData> {
+    egraph: &'a EGraph<L, N>,
+    iterations: &'a [Iteration<IterData>],
+    roots: &'a [Id],
+    stop_reason: &'a Option<StopReason>,
+    elapsed: Option<f64>,
+    scheduler: Option<SchedulerState>,
+}
+
+#[cfg(feature = "serde-1")]
+#[derive(serde::Deserialize)]
+#[serde(bound = "EGraph<L, N>: serde::Deserialize<'de>, IterData: serde::Deserialize<'de>")]
+struct Checkpoint<L: Language, N: Analysis<L>, IterData> {
+    egraph: EGraph<L, N>,
+    iterations: Vec<Iteration<IterData>>,
+    roots: Vec<Id>,
+    stop_reason: Option<StopReason>,
+    elapsed: Option<f64>,
+    scheduler: Option<SchedulerState>,
+}
+
+#[cfg(feature = "serde-1")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde-1")))]
+impl<L, N, IterData> Runner<L, N, IterData>
+where
+    L: Language,
+    N: Analysis<L>,
+    IterData: IterationData<L, N>,
+{
+    /// Save the progress of this `Runner` as JSON to `writer`.
+    ///
+    /// The checkpoint contains the egraph, [`roots`](Runner::roots),
+    /// [`iterations`](Runner::iterations), [`stop_reason`](Runner::stop_reason),
+    /// the time spent so far, and the state of the [`RewriteScheduler`]
+    /// (see [`RewriteScheduler::save_state`]).
+    /// It does not contain the configuration of the runner, i.e. the
+    /// limits, hooks, goals, cancel token or the scheduler itself.
+    pub fn save_checkpoint(&self, writer: impl std::io::Write) -> serde_json::Result<()>
+    where
+        EGraph<L, N>: serde::Serialize,
+        IterData: serde::Serialize,
+    {
+        let checkpoint = CheckpointRef {
+            egraph: &self.egraph,
+            iterations: &self.iterations,
+            roots: &self.roots,
+            stop_reason: &self.stop_reason,
+            elapsed: self.limits.start_time.map(|t| t.elapsed().as_secs_f64()),
+            scheduler: self.scheduler.save_state(),
+        };
+        serde_json::to_writer(writer, &checkpoint)
+    }
+
+    /// Restore progress saved by [`save_checkpoint`](Runner::save_checkpoint()).
+    ///
+    /// This replaces the egraph, [`roots`](Runner::roots),
+    /// [`iterations`](Runner::iterations) and [`stop_reason`](Runner::stop_reason)
+    /// of this `Runner`, and restores the scheduler state with
+    /// [`RewriteScheduler::load_state`].
+    /// The time limit continues from the time spent before the checkpoint.
+    ///
+    /// Configure the scheduler before loading a checkpoint.
+    /// Goals refer to the egraph, so add them after loading.
+    /// If the checkpoint was saved from a stopped runner, use
+    /// [`resume`](Runner::resume()) to keep going.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    ///
+    /// let mut runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap());
+    /// runner.step(rules).unwrap();
+    ///
+    /// let mut file = vec![];
+    /// runner.save_checkpoint(&mut file).unwrap();
+    ///
+    /// let restored = Runner::<SymbolLang, ()>::default()
+    ///     .load_checkpoint(file.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(restored.iterations.len(), 1);
+    /// assert_eq!(restored.egraph.total_size(), runner.egraph.total_size());
+    /// let restored = restored.run(rules);
+    /// ```
+    pub fn load_checkpoint(mut self, reader: impl std::io::Read) -> serde_json::Result<Self>
+    where
+        EGraph<L, N>: serde::de::DeserializeOwned,
+        IterData: serde::de::DeserializeOwned,
+    {
+        let checkpoint: Checkpoint<L, N, IterData> = serde_json::from_reader(reader)?;
+        if let Some(state) = checkpoint.scheduler {
+            self.scheduler
+                .load_state(state)
+                .map_err(serde::de::Error::custom)?;
+        }
+        self.egraph = checkpoint.egraph;
+        self.egraph.rebuild();
+        self.iterations = checkpoint.iterations;
+        self.roots = checkpoint.roots;
+        self.stop_reason = checkpoint.stop_reason;
+        self.limits.start_time = checkpoint.elapsed.map(|elapsed| {
+            let now = Instant::now();
+            now.checked_sub(Duration::from_secs_f64(elapsed))
+                .unwrap_or(now)
+        });
+        Ok(self)
+    }
+}
+
 fn check_rules<L, N>(rules: &[&Rewrite<L, N>]) {
     let mut name_counts = IndexMap::default();
     for rw in rules {
@@ -660,6 +1770,100 @@ fn check_rules<L, N>(rules: &[&Rewrite<L, N>]) {
     }
 }
 
+/// A handle used to cancel a [`Runner`], possibly from another thread.
+///
+/// Cloning a [`CancelToken`] gives another handle to the same flag, so
+/// you can keep one clone and give the other to
+/// [`with_cancel_token`](Runner::with_cancel_token()).
+/// Once cancelled, a token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a new, uncancelled token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every [`Runner`] using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// Returns `true` if [`cancel`](CancelToken::cancel()) has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Makes e-matching on this thread check this token until the guard
+    /// is dropped, see [`search_cancelled`].
+    fn check_while_searching(&self) -> SearchTokenGuard {
+        SearchTokenGuard(SEARCH_TOKEN.with(|token| token.replace(Some(self.clone()))))
+    }
+}
+
+thread_local! {
+    static SEARCH_TOKEN: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous [`SEARCH_TOKEN`] when dropped.
+struct SearchTokenGuard(Option<CancelToken>);
+
+impl Drop for SearchTokenGuard {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        SEARCH_TOKEN.with(|token| *token.borrow_mut() = outer);
+    }
+}
+
+/// Returns `true` if the [`Runner`] searching on this thread has been
+/// cancelled, so long e-matching loops can stop early.
+pub(crate) fn search_cancelled() -> bool {
+    SEARCH_TOKEN.with(|token| token.borrow().as_ref().map_or(false, |t| t.is_cancelled()))
+}
+
+/// The state of a [`RewriteScheduler`], saved in a [`Runner`] checkpoint
+/// by [`save_state`](RewriteScheduler::save_state()).
+///
+/// This is opaque so that [`RewriteScheduler`] is the same with or
+/// without the `serde-1` feature; with it, make one with
+/// `SchedulerState::new` and read it back with `SchedulerState::get`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-1", serde(transparent))]
+pub struct SchedulerState {
+    #[cfg(feature = "serde-1")]
+    value: serde_json::Value,
+}
+
+#[cfg(feature = "serde-1")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde-1")))]
+impl SchedulerState {
+    /// Saves `state`, failing if it cannot be serialized.
+    pub fn new<T: serde::Serialize>(state: &T) -> Result<Self, SchedulerStateError> {
+        Ok(Self {
+            value: serde_json::to_value(state)?,
+        })
+    }
+
+    /// Reads back the state given to [`new`](SchedulerState::new).
+    pub fn get<T: serde::de::DeserializeOwned>(self) -> Result<T, SchedulerStateError> {
+        Ok(serde_json::from_value(self.value)?)
+    }
+}
+
+/// An error from saving or loading a [`SchedulerState`].
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("bad scheduler state: {0}")]
+pub struct SchedulerStateError(String);
+
+#[cfg(feature = "serde-1")]
+impl From<serde_json::Error> for SchedulerStateError {
+    fn from(err: serde_json::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
 /** A way to customize how a [`Runner`] runs [`Rewrite`]s.
 
 This gives you a way to prevent certain [`Rewrite`]s from exploding
@@ -703,9 +1907,15 @@ where
     /// [`Self::search_rewrite`] for each rewrite,
     /// and checks [`RunnerLimits::check_limits`] after each.
     ///
+    /// Search each rewrite through [`RunnerLimits::timed_search`] to
+    /// record its search time in its [`RuleProfile`] and to stop
+    /// e-matching early once the runner is cancelled.
+    ///
     /// Returning an error will stop the runner.
     ///
-    /// You might use this to implement parallel rule application:
+    /// You might use this to implement parallel rule application.
+    /// With the `rayon` feature, `egg` provides `ParallelScheduler`,
+    /// which does this for you; the following is a sketch of the idea:
     /// ```
     /// # use egg::*;
     /// pub struct ParallelRewriteScheduler;
@@ -740,13 +1950,41 @@ where
     ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
         let mut matches = Vec::new();
         for rw in rewrites {
-            let ms = self.search_rewrite(iteration, egraph, rw);
+            let ms = limits.timed_search(rw.name, || self.search_rewrite(iteration, egraph, rw));
             matches.push(ms);
             limits.check_limits(iteration, egraph)?;
         }
         Ok(matches)
     }
 
+    /// Return the names of the rules this scheduler banned since the last
+    /// call, one entry per ban.
+    /// The [`Runner`] calls this after searching to fill in
+    /// [`RuleProfile::bans`].
+    ///
+    /// Default implementation returns no bans.
+    fn take_bans(&mut self) -> Vec<Symbol> {
+        vec![]
+    }
+
+    /// Save the state of this scheduler to be stored in a [`Runner`]
+    /// checkpoint (see `Runner::save_checkpoint`, with the `serde-1`
+    /// feature).
+    ///
+    /// Default implementation saves nothing.
+    fn save_state(&self) -> Option<SchedulerState> {
+        None
+    }
+
+    /// Restore the state saved by
+    /// [`save_state`](RewriteScheduler::save_state()) when a [`Runner`]
+    /// checkpoint is loaded (see `Runner::load_checkpoint`).
+    ///
+    /// Default implementation does nothing.
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        Ok(())
+    }
+
     /// A hook allowing you to customize rewrite application behavior.
     /// Useful to implement rule management.
     ///
@@ -801,11 +2039,13 @@ where
 pub struct BackoffScheduler {
     default_match_limit: usize,
     default_ban_length: usize,
-    stats: IndexMap<Symbol, RuleStats>,
+    stats: IndexMap<Symbol, BackoffStats>,
+    bans: Vec<Symbol>,
 }
 
 #[derive(Debug)]
-struct RuleStats {
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+struct BackoffStats {
     times_applied: usize,
     banned_until: usize,
     times_banned: usize,
@@ -828,11 +2068,11 @@ impl BackoffScheduler {
         self
     }
 
-    fn rule_stats(&mut self, name: Symbol) -> &mut RuleStats {
+    fn rule_stats(&mut self, name: Symbol) -> &mut BackoffStats {
         if self.stats.contains_key(&name) {
             &mut self.stats[&name]
         } else {
-            self.stats.entry(name).or_insert(RuleStats {
+            self.stats.entry(name).or_insert(BackoffStats {
                 times_applied: 0,
                 banned_until: 0,
                 times_banned: 0,
@@ -842,41 +2082,56 @@ impl BackoffScheduler {
         }
     }
 
-    /// Never ban a particular rule.
-    pub fn do_not_ban(mut self, name: impl Into<Symbol>) -> Self {
-        self.rule_stats(name.into()).match_limit = usize::MAX;
-        self
-    }
+    /// Returns the number of matches the rule may find in this iteration
+    /// without being banned, or `None` if it is currently banned.
+    fn match_limit(&mut self, iteration: usize, name: Symbol) -> Option<usize> {
+        let stats = self.rule_stats(name);
 
-    /// Set the initial match limit for a rule.
-    pub fn rule_match_limit(mut self, name: impl Into<Symbol>, limit: usize) -> Self {
-        self.rule_stats(name.into()).match_limit = limit;
-        self
-    }
+        if iteration < stats.banned_until {
+            debug!(
+                "Skipping {} ({}-{}), banned until {}...",
+                name, stats.times_applied, stats.times_banned, stats.banned_until,
+            );
+            return None;
+        }
 
-    /// Set the initial ban length for a rule.
-    pub fn rule_ban_length(mut self, name: impl Into<Symbol>, length: usize) -> Self {
-        self.rule_stats(name.into()).ban_length = length;
-        self
+        let threshold = stats
+            .match_limit
+            .checked_shl(stats.times_banned as u32)
+            .unwrap();
+        Some(threshold)
     }
-}
 
-impl Default for BackoffScheduler {
-    fn default() -> Self {
-        Self {
-            stats: Default::default(),
-            default_match_limit: 1_000,
-            default_ban_length: 5,
+    /// Records that the rule found `total_len` matches, banning it if
+    /// that is over `threshold`.
+    /// Returns whether the matches should be applied.
+    fn record_matches(
+        &mut self,
+        iteration: usize,
+        name: Symbol,
+        threshold: usize,
+        total_len: usize,
+    ) -> bool {
+        let stats = self.rule_stats(name);
+        if total_len > threshold {
+            let ban_length = stats.ban_length << stats.times_banned;
+            stats.times_banned += 1;
+            stats.banned_until = iteration + ban_length;
+            info!(
+                "Banning {} ({}-{}) for {} iters: {} < {}",
+                name, stats.times_applied, stats.times_banned, ban_length, threshold, total_len,
+            );
+            self.bans.push(name);
+            false
+        } else {
+            stats.times_applied += 1;
+            true
         }
     }
-}
 
-impl<L, N> RewriteScheduler<L, N> for BackoffScheduler
-where
-    L: Language,
-    N: Analysis<L>,
-{
-    fn can_stop(&mut self, iteration: usize) -> bool {
+    /// If any rules are banned, fast-forward their bans so that at least
+    /// one is unbanned and return `false`, otherwise return `true`.
+    fn fast_forward(&mut self, iteration: usize) -> bool {
         let n_stats = self.stats.len();
 
         let mut banned: Vec<_> = self
@@ -918,47 +2173,971 @@ where
         }
     }
 
-    fn search_rewrite<'a>(
-        &mut self,
-        iteration: usize,
-        egraph: &EGraph<L, N>,
-        rewrite: &'a Rewrite<L, N>,
-    ) -> Vec<SearchMatches<'a, L>> {
-        let stats = self.rule_stats(rewrite.name);
+    /// Never ban a particular rule.
+    pub fn do_not_ban(mut self, name: impl Into<Symbol>) -> Self {
+        self.rule_stats(name.into()).match_limit = usize::MAX;
+        self
+    }
 
-        if iteration < stats.banned_until {
-            debug!(
-                "Skipping {} ({}-{}), banned until {}...",
-                rewrite.name, stats.times_applied, stats.times_banned, stats.banned_until,
-            );
-            return vec![];
+    /// Set the initial match limit for a rule.
+    pub fn rule_match_limit(mut self, name: impl Into<Symbol>, limit: usize) -> Self {
+        self.rule_stats(name.into()).match_limit = limit;
+        self
+    }
+
+    /// Set the initial ban length for a rule.
+    pub fn rule_ban_length(mut self, name: impl Into<Symbol>, length: usize) -> Self {
+        self.rule_stats(name.into()).ban_length = length;
+        self
+    }
+}
+
+impl Default for BackoffScheduler {
+    fn default() -> Self {
+        Self {
+            stats: Default::default(),
+            default_match_limit: 1_000,
+            default_ban_length: 5,
+            bans: vec![],
         }
+    }
+}
 
-        let threshold = stats
-            .match_limit
-            .checked_shl(stats.times_banned as u32)
-            .unwrap();
+impl<L, N> RewriteScheduler<L, N> for BackoffScheduler
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        self.fast_forward(iteration)
+    }
+
+    fn take_bans(&mut self) -> Vec<Symbol> {
+        std::mem::take(&mut self.bans)
+    }
+
+    #[cfg(feature = "serde-1")]
+    fn save_state(&self) -> Option<SchedulerState> {
+        SchedulerState::new(&self.stats).ok()
+    }
+
+    #[cfg(feature = "serde-1")]
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        self.stats = state.get()?;
+        Ok(())
+    }
+
+    fn search_rewrite<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrite: &'a Rewrite<L, N>,
+    ) -> Vec<SearchMatches<'a, L>> {
+        let threshold = match self.match_limit(iteration, rewrite.name) {
+            Some(threshold) => threshold,
+            None => return vec![],
+        };
         let matches = rewrite.search_with_limit(egraph, threshold.saturating_add(1));
         let total_len: usize = matches.iter().map(|m| m.substs.len()).sum();
-        if total_len > threshold {
-            let ban_length = stats.ban_length << stats.times_banned;
-            stats.times_banned += 1;
-            stats.banned_until = iteration + ban_length;
-            info!(
-                "Banning {} ({}-{}) for {} iters: {} < {}",
-                rewrite.name,
-                stats.times_applied,
-                stats.times_banned,
-                ban_length,
-                threshold,
-                total_len,
-            );
+        if self.record_matches(iteration, rewrite.name, threshold, total_len) {
+            matches
+        } else {
             vec![]
+        }
+    }
+}
+
+/// A [`RewriteScheduler`] that treats rules as arms of a multi-armed
+/// bandit and gives the productive ones more of the search budget.
+///
+/// Each iteration, the scheduler has a total match budget that it splits
+/// among the rules.
+/// A rule's productivity is the number of its matches that changed the
+/// e-graph when applied, per second spent searching for it.
+/// Rules are ranked with the
+/// [UCB1](https://en.wikipedia.org/wiki/Multi-armed_bandit#Upper_confidence_bound_(UCB)_methods)
+/// rule, so rules that are making progress rank higher, while rarely
+/// searched rules still get explored.
+/// Only the best ranked rules are searched, as many as the budget allows
+/// at the [minimum match limit](BanditScheduler::with_min_match_limit()),
+/// and the budget is split among them by rank.
+/// Rules that have never been searched are searched first, sharing the
+/// whole budget.
+///
+/// A rule stops searching once it has found as many matches as its
+/// budget, so the budget also bounds the time spent searching.
+/// The e-classes are searched in a random order drawn from a seeded
+/// generator (see [`with_seed`](BanditScheduler::with_seed())), so
+/// matches in e-classes searched last are not starved by the ones found
+/// first.
+/// Before the [`Runner`] is allowed to stop as saturated, the scheduler
+/// runs one iteration searching every rule with no budget, so saturation
+/// is still exact.
+///
+/// Since the allocation depends on measured search times, runs with the
+/// same seed are not always identical.
+///
+/// [`BanditScheduler`] is configurable in the builder-pattern style.
+///
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+/// ];
+///
+/// let scheduler = BanditScheduler::default().with_match_budget(1_000);
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+///     .with_scheduler(scheduler)
+///     .run(rules);
+/// ```
+#[derive(Debug)]
+pub struct BanditScheduler {
+    match_budget: usize,
+    min_match_limit: usize,
+    exploration: f64,
+    arms: IndexMap<Symbol, ArmStats>,
+    rng: Rng,
+    truncated: bool,
+    unlimited: bool,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+struct ArmStats {
+    pulls: usize,
+    search_time: f64,
+    applied: usize,
+}
+
+impl ArmStats {
+    fn rate(&self) -> f64 {
+        self.applied as f64 / self.search_time.max(1e-9)
+    }
+}
+
+impl BanditScheduler {
+    /// Set the total number of matches shared by all rules in each iteration.
+    /// Default: 10,000
+    pub fn with_match_budget(mut self, budget: usize) -> Self {
+        self.match_budget = budget;
+        self
+    }
+
+    /// Set the smallest number of matches any rule searched in an iteration
+    /// is allowed.
+    /// This also limits how many rules are searched in each iteration.
+    /// Default: 100
+    pub fn with_min_match_limit(mut self, limit: usize) -> Self {
+        self.min_match_limit = limit;
+        self
+    }
+
+    /// Set how much the scheduler favors trying rules it knows little
+    /// about over rules known to be productive.
+    /// Default: `2.0_f64.sqrt()`
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    /// Set the seed used to pick the order e-classes are searched in.
+    /// Default: 0
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Pick the rules to search this iteration and compute their match
+    /// limits, or `None` for the rules that are not searched.
+    fn match_limits(&mut self, names: &[Symbol]) -> Vec<Option<usize>> {
+        if self.unlimited {
+            return vec![Some(usize::MAX); names.len()];
+        }
+
+        for name in names {
+            self.arms.entry(*name).or_default();
+        }
+        let arms: Vec<&ArmStats> = names.iter().map(|name| &self.arms[name]).collect();
+        let untried = arms.iter().filter(|a| a.pulls == 0).count();
+        if untried > 0 {
+            let limit = (self.match_budget / untried).max(self.min_match_limit);
+            return arms
+                .iter()
+                .map(|arm| if arm.pulls == 0 { Some(limit) } else { None })
+                .collect();
+        }
+
+        let total_pulls: usize = arms.iter().map(|a| a.pulls).sum();
+        let max_rate = arms.iter().map(|a| a.rate()).fold(0.0, f64::max);
+        let scores: Vec<f64> = arms
+            .iter()
+            .map(|arm| {
+                let exploit = if max_rate > 0.0 {
+                    arm.rate() / max_rate
+                } else {
+                    0.0
+                };
+                let explore = ((total_pulls as f64).ln() / arm.pulls as f64).sqrt();
+                exploit + self.exploration * explore
+            })
+            .collect();
+
+        let n_pulled = (self.match_budget / self.min_match_limit.max(1)).clamp(1, names.len());
+        let mut pulled: Vec<usize> = (0..names.len()).collect();
+        pulled.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        pulled.truncate(n_pulled);
+
+        let total_score: f64 = pulled.iter().map(|&i| scores[i]).sum();
+        let mut limits = vec![None; names.len()];
+        for i in pulled {
+            let share = if total_score > 0.0 {
+                scores[i] / total_score
+            } else {
+                1.0 / n_pulled as f64
+            };
+            let limit = (self.match_budget as f64 * share) as usize;
+            limits[i] = Some(limit.max(self.min_match_limit));
+        }
+        limits
+    }
+}
+
+impl Default for BanditScheduler {
+    fn default() -> Self {
+        Self {
+            match_budget: 10_000,
+            min_match_limit: 100,
+            exploration: 2.0_f64.sqrt(),
+            arms: Default::default(),
+            rng: Rng::new(0),
+            truncated: false,
+            unlimited: false,
+        }
+    }
+}
+
+impl<L, N> RewriteScheduler<L, N> for BanditScheduler
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn can_stop(&mut self, _iteration: usize) -> bool {
+        if self.truncated {
+            info!("Some rules hit their match budget, searching without budgets");
+            self.unlimited = true;
+            false
         } else {
-            stats.times_applied += 1;
-            matches
+            true
         }
     }
+
+    #[cfg(feature = "serde-1")]
+    fn save_state(&self) -> Option<SchedulerState> {
+        SchedulerState::new(&self.arms).ok()
+    }
+
+    #[cfg(feature = "serde-1")]
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        self.arms = state.get()?;
+        Ok(())
+    }
+
+    fn search_rewrites<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrites: &[&'a Rewrite<L, N>],
+        limits: &RunnerLimits,
+    ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
+        let names: Vec<Symbol> = rewrites.iter().map(|rw| rw.name).collect();
+        let match_limits = self.match_limits(&names);
+        self.truncated = false;
+        self.unlimited = false;
+
+        let mut eclasses: Vec<Id> = egraph.classes().map(|c| c.id).collect();
+        let mut matches = Vec::new();
+        for (rw, limit) in rewrites.iter().zip(match_limits) {
+            let limit = match limit {
+                Some(limit) => limit,
+                None => {
+                    self.truncated = true;
+                    matches.push(vec![]);
+                    continue;
+                }
+            };
+
+            for k in (1..eclasses.len()).rev() {
+                let chosen = self.rng.below(k + 1);
+                eclasses.swap(k, chosen);
+            }
+            let start = Instant::now();
+            let ms = limits.timed_search(rw.name, || {
+                rw.searcher
+                    .search_eclasses_with_limit(egraph, &eclasses, limit)
+            });
+            let total_len: usize = ms.iter().map(|m| m.substs.len()).sum();
+
+            let arm = self.arms.entry(rw.name).or_default();
+            arm.pulls += 1;
+            arm.search_time += start.elapsed().as_secs_f64();
+            if total_len >= limit {
+                debug!("{} hit its budget of {} matches", rw.name, limit);
+                self.truncated = true;
+            }
+
+            matches.push(ms);
+            limits.check_limits(iteration, egraph)?;
+        }
+        Ok(matches)
+    }
+
+    fn apply_rewrite(
+        &mut self,
+        _iteration: usize,
+        egraph: &mut EGraph<L, N>,
+        rewrite: &Rewrite<L, N>,
+        matches: Vec<SearchMatches<L>>,
+    ) -> usize {
+        let applied = rewrite.apply(egraph, &matches).len();
+        self.arms.entry(rewrite.name).or_default().applied += applied;
+        applied
+    }
+}
+
+/// A [`RewriteScheduler`] that applies a sample of the matches of rules
+/// that find too many, instead of banning them.
+///
+/// For each rewrite, there is a configurable match limit.
+/// If a search finds more matches than this, only `limit` of them are
+/// applied in this iteration.
+/// By default these are picked uniformly at random using a seeded
+/// generator (see [`with_seed`](SamplingScheduler::with_seed())), so
+/// runs are reproducible.
+/// With [`with_ranking`](SamplingScheduler::with_ranking()), the matches
+/// with the lowest rank are kept instead, which lets you prefer matches
+/// based on the [`Analysis`] data of the e-classes involved.
+///
+/// Unlike [`BackoffScheduler`], explosive rules like associativity keep
+/// making progress every iteration, just under a budget.
+/// Before the [`Runner`] is allowed to stop as saturated, the scheduler
+/// runs one iteration applying every match, so saturation is still exact.
+///
+/// [`SamplingScheduler`] is configurable in the builder-pattern style.
+///
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+/// ];
+///
+/// let scheduler = SamplingScheduler::default()
+///     .with_initial_match_limit(100)
+///     .rule_match_limit("assoc-add", 10)
+///     .with_seed(42);
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+///     .with_scheduler(scheduler)
+///     .run(rules);
+/// ```
+pub struct SamplingScheduler<L: Language, N: Analysis<L>> {
+    default_match_limit: usize,
+    match_limits: IndexMap<Symbol, usize>,
+    rng: Rng,
+    #[allow(clippy::type_complexity)]
+    ranking: Option<Box<dyn Fn(&EGraph<L, N>, Id, &Subst) -> f64>>,
+    sampled: bool,
+    unlimited: bool,
+}
+
+impl<L: Language, N: Analysis<L>> SamplingScheduler<L, N> {
+    /// Set the number of matches applied per rule and iteration.
+    /// Default: 1,000
+    pub fn with_initial_match_limit(mut self, limit: usize) -> Self {
+        self.default_match_limit = limit;
+        self
+    }
+
+    /// Set the match limit for a rule.
+    pub fn rule_match_limit(mut self, name: impl Into<Symbol>, limit: usize) -> Self {
+        self.match_limits.insert(name.into(), limit);
+        self
+    }
+
+    /// Never sample a particular rule, always applying all of its matches.
+    pub fn do_not_sample(self, name: impl Into<Symbol>) -> Self {
+        self.rule_match_limit(name, usize::MAX)
+    }
+
+    /// Set the seed used to sample matches.
+    /// Default: 0
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Keep the matches with the lowest rank instead of a random sample.
+    ///
+    /// The ranking function is given the e-class the match was found in
+    /// and its substitution.
+    /// Ties are broken by the order the matches were found in.
+    pub fn with_ranking(
+        mut self,
+        ranking: impl Fn(&EGraph<L, N>, Id, &Subst) -> f64 + 'static,
+    ) -> Self {
+        self.ranking = Some(Box::new(ranking));
+        self
+    }
+
+    /// Pick which `limit` of the `matches` to keep.
+    fn sample(
+        &mut self,
+        egraph: &EGraph<L, N>,
+        matches: &mut Vec<SearchMatches<L>>,
+        total_len: usize,
+        limit: usize,
+    ) {
+        let ranking = match &self.ranking {
+            Some(ranking) => ranking,
+            None => return sample_matches(&mut self.rng, matches, limit),
+        };
+        let indices = match_indices(matches);
+        debug_assert_eq!(indices.len(), total_len);
+
+        let rank = |&(i, j): &(usize, usize)| {
+            let m: &SearchMatches<L> = &matches[i];
+            ranking(egraph, m.eclass, &m.substs[j])
+        };
+        let mut ranked: Vec<(f64, (usize, usize))> =
+            indices.iter().map(|ij| (rank(ij), *ij)).collect();
+        ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let indices = ranked.into_iter().map(|(_, ij)| ij).collect();
+        retain_matches(matches, indices, limit);
+    }
+}
+
+/// The `(match, subst)` index of every substitution in `matches`.
+fn match_indices<L: Language>(matches: &[SearchMatches<L>]) -> Vec<(usize, usize)> {
+    matches
+        .iter()
+        .enumerate()
+        .flat_map(|(i, m)| (0..m.substs.len()).map(move |j| (i, j)))
+        .collect()
+}
+
+/// Keeps a uniform random sample of `limit` of the `matches`.
+fn sample_matches<L: Language>(rng: &mut Rng, matches: &mut Vec<SearchMatches<L>>, limit: usize) {
+    let mut indices = match_indices(matches);
+    // a partial Fisher-Yates shuffle
+    for k in 0..limit.min(indices.len()) {
+        let chosen = k + rng.below(indices.len() - k);
+        indices.swap(k, chosen);
+    }
+    retain_matches(matches, indices, limit);
+}
+
+/// Keeps the first `limit` of the substitutions at `indices`.
+fn retain_matches<L: Language>(
+    matches: &mut Vec<SearchMatches<L>>,
+    mut indices: Vec<(usize, usize)>,
+    limit: usize,
+) {
+    indices.truncate(limit);
+    indices.sort_unstable();
+
+    let mut keep = indices.into_iter().peekable();
+    for (i, m) in matches.iter_mut().enumerate() {
+        let mut j = 0;
+        m.substs.retain(|_| {
+            let kept = keep.next_if_eq(&(i, j)).is_some();
+            j += 1;
+            kept
+        });
+    }
+    matches.retain(|m| !m.substs.is_empty());
+}
+
+impl<L: Language, N: Analysis<L>> Default for SamplingScheduler<L, N> {
+    fn default() -> Self {
+        Self {
+            default_match_limit: 1_000,
+            match_limits: Default::default(),
+            rng: Rng::new(0),
+            ranking: None,
+            sampled: false,
+            unlimited: false,
+        }
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Debug for SamplingScheduler<L, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SamplingScheduler")
+            .field("default_match_limit", &self.default_match_limit)
+            .field("match_limits", &self.match_limits)
+            .field("rng", &self.rng)
+            .field("ranking", &self.ranking.as_ref().map(|_| "<dyn Fn ..>"))
+            .field("sampled", &self.sampled)
+            .field("unlimited", &self.unlimited)
+            .finish()
+    }
+}
+
+impl<L, N> RewriteScheduler<L, N> for SamplingScheduler<L, N>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn can_stop(&mut self, _iteration: usize) -> bool {
+        if self.sampled {
+            info!("Some rules were sampled, applying all matches");
+            self.unlimited = true;
+            false
+        } else {
+            true
+        }
+    }
+
+    fn search_rewrites<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrites: &[&'a Rewrite<L, N>],
+        limits: &RunnerLimits,
+    ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
+        let unlimited = std::mem::take(&mut self.unlimited);
+        self.sampled = false;
+
+        let mut matches = Vec::new();
+        for rw in rewrites {
+            let mut ms = limits.timed_search(rw.name, || rw.search(egraph));
+            let limit = match self.match_limits.get(&rw.name) {
+                Some(limit) => *limit,
+                None => self.default_match_limit,
+            };
+            let total_len: usize = ms.iter().map(|m| m.substs.len()).sum();
+            if !unlimited && total_len > limit {
+                debug!("Sampling {} of {} matches of {}", limit, total_len, rw.name);
+                self.sample(egraph, &mut ms, total_len, limit);
+                self.sampled = true;
+            }
+            matches.push(ms);
+            limits.check_limits(iteration, egraph)?;
+        }
+        Ok(matches)
+    }
+}
+
+/// A [`RewriteScheduler`] that implements semi-naive e-matching.
+///
+/// The first time a rewrite is searched, [`IncrementalScheduler`] finds
+/// all of its matches.
+/// After that, it only finds the matches that involve an e-class touched
+/// since that rewrite was last searched, using
+/// [`Rewrite::search_touched`].
+/// Matches that were already found once are not found again, so
+/// each iteration does work proportional to what changed rather than to
+/// the size of the whole [`EGraph`].
+/// A rewrite only counts as searched once all the matches found for it
+/// reach [`apply_rewrite`](RewriteScheduler::apply_rewrite()), so
+/// matches dropped by an
+/// [after search hook](Runner::with_after_search_hook()), or left
+/// unapplied because a limit was hit, are found again the next time.
+///
+/// An e-class is touched when it is created, merged with another e-class,
+/// or has its analysis data changed by the egraph
+/// (see [`EGraph::generation`]).
+/// This misses new matches in two cases, so avoid this scheduler if your
+/// rules rely on them:
+/// - a [`Condition`] or custom [`Applier`] that failed may succeed later
+///   because of changes to e-classes the match does not involve;
+/// - [`Analysis::modify`] changes the data of an e-class directly.
+///
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+/// ];
+///
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+///     .with_scheduler(IncrementalScheduler::default())
+///     .run(rules);
+/// assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+/// ```
+#[derive(Debug, Default)]
+pub struct IncrementalScheduler {
+    last_searched: IndexMap<Symbol, usize>,
+    /// The generation each rewrite was searched at, and the number of
+    /// matches found, until they are applied.
+    searched: IndexMap<Symbol, (usize, usize)>,
+}
+
+impl<L, N> RewriteScheduler<L, N> for IncrementalScheduler
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn search_rewrite<'a>(
+        &mut self,
+        _iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrite: &'a Rewrite<L, N>,
+    ) -> Vec<SearchMatches<'a, L>> {
+        let matches = match self.last_searched.get(&rewrite.name) {
+            None => rewrite.search(egraph),
+            Some(&generation) => rewrite.search_touched(egraph, generation),
+        };
+        let n_matches = matches.iter().map(|m| m.substs.len()).sum();
+        self.searched
+            .insert(rewrite.name, (egraph.generation(), n_matches));
+        matches
+    }
+
+    fn apply_rewrite(
+        &mut self,
+        _iteration: usize,
+        egraph: &mut EGraph<L, N>,
+        rewrite: &Rewrite<L, N>,
+        matches: Vec<SearchMatches<L>>,
+    ) -> usize {
+        if let Some((generation, n_matches)) = self.searched.swap_remove(&rewrite.name) {
+            if matches.iter().map(|m| m.substs.len()).sum::<usize>() == n_matches {
+                self.last_searched.insert(rewrite.name, generation);
+            }
+        }
+        rewrite.apply(egraph, &matches).len()
+    }
+
+    #[cfg(feature = "serde-1")]
+    fn save_state(&self) -> Option<SchedulerState> {
+        SchedulerState::new(&self.last_searched).ok()
+    }
+
+    #[cfg(feature = "serde-1")]
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        self.last_searched = state.get()?;
+        Ok(())
+    }
+}
+
+/// A [`RewriteScheduler`] that searches all the rewrites with
+/// [`Pattern`] left-hand sides at once, with a [`PatternSet`].
+///
+/// When many rules start the same way, this saves searching for the
+/// same e-nodes over and over.
+/// The [`PatternSet`] is compiled the first time the rules are searched,
+/// and again whenever the set of rules changes, for example between
+/// the phases of [`Runner::run_schedule`].
+///
+/// A rewrite is searched together with the others if
+/// [`Searcher::get_pattern_ast`] returns its pattern;
+/// other rewrites are searched one at a time.
+/// The time spent searching the shared rewrites is split evenly among
+/// them in their [`RuleProfile`]s.
+/// The [`RunnerLimits`] are checked after the shared search and after
+/// each search of a single rewrite.
+///
+/// With [`with_backoff`](SharedScheduler::with_backoff()), it bans
+/// explosive rules the same way a [`BackoffScheduler`] does; banned
+/// rules are left out of the [`PatternSet`] until their ban ends.
+///
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+///     rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+/// ];
+///
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c 0)))".parse().unwrap())
+///     .with_scheduler(SharedScheduler::default())
+///     .run(rules);
+/// assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+///
+/// let scheduler = SharedScheduler::default().with_backoff(BackoffScheduler::default());
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c 0)))".parse().unwrap())
+///     .with_scheduler(scheduler)
+///     .run(rules);
+/// ```
+#[derive(Debug)]
+pub struct SharedScheduler<L: Language> {
+    compiled: Option<(Vec<Symbol>, PatternSet<L>)>,
+    backoff: Option<BackoffScheduler>,
+}
+
+impl<L: Language> Default for SharedScheduler<L> {
+    fn default() -> Self {
+        Self {
+            compiled: None,
+            backoff: None,
+        }
+    }
+}
+
+impl<L: Language> SharedScheduler<L> {
+    /// Ban rules that find too many matches according to the given
+    /// [`BackoffScheduler`].
+    pub fn with_backoff(mut self, backoff: BackoffScheduler) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+}
+
+impl<L, N> RewriteScheduler<L, N> for SharedScheduler<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        match &mut self.backoff {
+            Some(backoff) => backoff.fast_forward(iteration),
+            None => true,
+        }
+    }
+
+    fn take_bans(&mut self) -> Vec<Symbol> {
+        match &mut self.backoff {
+            Some(backoff) => std::mem::take(&mut backoff.bans),
+            None => vec![],
+        }
+    }
+
+    fn save_state(&self) -> Option<SchedulerState> {
+        let backoff = self.backoff.as_ref()?;
+        RewriteScheduler::<L, N>::save_state(backoff)
+    }
+
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        match &mut self.backoff {
+            Some(backoff) => RewriteScheduler::<L, N>::load_state(backoff, state),
+            None => Ok(()),
+        }
+    }
+
+    fn search_rewrites<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrites: &[&'a Rewrite<L, N>],
+        limits: &RunnerLimits,
+    ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
+        let thresholds: Vec<Option<usize>> = match &mut self.backoff {
+            Some(backoff) => rewrites
+                .iter()
+                .map(|rw| backoff.match_limit(iteration, rw.name))
+                .collect(),
+            None => vec![Some(usize::MAX); rewrites.len()],
+        };
+
+        let shared: Vec<(usize, &'a PatternAst<L>)> = rewrites
+            .iter()
+            .zip(&thresholds)
+            .enumerate()
+            .filter(|(_, (_, threshold))| threshold.is_some())
+            .filter_map(|(i, (rw, _))| Some((i, rw.searcher.get_pattern_ast()?)))
+            .collect();
+
+        let names: Vec<Symbol> = shared.iter().map(|&(i, _)| rewrites[i].name).collect();
+        if !matches!(&self.compiled, Some((compiled, _)) if *compiled == names) {
+            let patterns = shared.iter().map(|(_, ast)| Pattern::new((*ast).clone()));
+            self.compiled = Some((names, PatternSet::new(patterns)));
+        }
+        let (_, set) = self.compiled.as_ref().unwrap();
+
+        let mut matches: Vec<Option<Vec<SearchMatches<'a, L>>>> =
+            rewrites.iter().map(|_| None).collect();
+        let limit = shared
+            .iter()
+            .filter_map(|&(i, _)| thresholds[i])
+            .max()
+            .unwrap_or(0)
+            .saturating_add(1);
+        let start = Instant::now();
+        let shared_matches = {
+            let _guard = limits
+                .cancel_token
+                .as_ref()
+                .map(|t| t.check_while_searching());
+            set.search_with_limit(egraph, limit)
+        };
+        let seconds = start.elapsed().as_secs_f64() / shared.len().max(1) as f64;
+        for &(i, _) in &shared {
+            limits.search_profile.record(rewrites[i].name, seconds);
+        }
+        limits.check_limits(iteration, egraph)?;
+
+        for ((i, ast), ms) in shared.iter().zip(shared_matches) {
+            let ms = ms
+                .into_iter()
+                .map(|m| SearchMatches {
+                    eclass: m.eclass,
+                    substs: m.substs,
+                    ast: Some(Cow::Borrowed(*ast)),
+                })
+                .collect();
+            matches[*i] = Some(ms);
+        }
+        let mut matches = matches
+            .into_iter()
+            .zip(rewrites)
+            .zip(&thresholds)
+            .map(|((ms, rw), threshold)| match (ms, threshold) {
+                (Some(ms), _) => Ok(ms),
+                (None, Some(threshold)) => {
+                    let ms = limits.timed_search(rw.name, || {
+                        rw.search_with_limit(egraph, threshold.saturating_add(1))
+                    });
+                    limits.check_limits(iteration, egraph)?;
+                    Ok(ms)
+                }
+                (None, None) => Ok(vec![]),
+            })
+            .collect::<RunnerResult<Vec<_>>>()?;
+
+        if let Some(backoff) = &mut self.backoff {
+            for ((rw, threshold), ms) in rewrites.iter().zip(&thresholds).zip(&mut matches) {
+                if let Some(threshold) = *threshold {
+                    let total_len: usize = ms.iter().map(|m| m.substs.len()).sum();
+                    if !backoff.record_matches(iteration, rw.name, threshold, total_len) {
+                        ms.clear();
+                    }
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+/// A [`RewriteScheduler`] that searches all the rewrites in parallel
+/// using [`rayon`](https://docs.rs/rayon).
+///
+/// By default it runs every rewrite every time, like [`SimpleScheduler`].
+/// Use [`with_backoff`](ParallelScheduler::with_backoff()) to ban
+/// explosive rules the same way a [`BackoffScheduler`] does.
+///
+/// The [`RunnerLimits`] are checked after each search, and once they are
+/// hit no more searches are started.
+/// Rewrites are still applied one at a time, since that mutates the
+/// [`EGraph`].
+///
+/// This requires the `rayon` feature.
+///
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+/// ];
+///
+/// let scheduler = ParallelScheduler::default().with_backoff(BackoffScheduler::default());
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+///     .with_scheduler(scheduler)
+///     .run(rules);
+/// ```
+#[cfg(feature = "rayon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+#[derive(Debug, Default)]
+pub struct ParallelScheduler {
+    backoff: Option<BackoffScheduler>,
+}
+
+#[cfg(feature = "rayon")]
+impl ParallelScheduler {
+    /// Ban rules that find too many matches according to the given
+    /// [`BackoffScheduler`].
+    pub fn with_backoff(mut self, backoff: BackoffScheduler) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<L, N> RewriteScheduler<L, N> for ParallelScheduler
+where
+    L: Language + Send + Sync,
+    L::Discriminant: Sync,
+    N: Analysis<L> + Sync,
+    N::Data: Sync,
+{
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        match &mut self.backoff {
+            Some(backoff) => backoff.fast_forward(iteration),
+            None => true,
+        }
+    }
+
+    fn take_bans(&mut self) -> Vec<Symbol> {
+        match &mut self.backoff {
+            Some(backoff) => std::mem::take(&mut backoff.bans),
+            None => vec![],
+        }
+    }
+
+    fn save_state(&self) -> Option<SchedulerState> {
+        let backoff = self.backoff.as_ref()?;
+        RewriteScheduler::<L, N>::save_state(backoff)
+    }
+
+    fn load_state(&mut self, state: SchedulerState) -> Result<(), SchedulerStateError> {
+        match &mut self.backoff {
+            Some(backoff) => RewriteScheduler::<L, N>::load_state(backoff, state),
+            None => Ok(()),
+        }
+    }
+
+    fn search_rewrites<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, N>,
+        rewrites: &[&'a Rewrite<L, N>],
+        limits: &RunnerLimits,
+    ) -> RunnerResult<Vec<Vec<SearchMatches<'a, L>>>> {
+        use rayon::prelude::*;
+
+        let thresholds: Vec<Option<usize>> = match &mut self.backoff {
+            Some(backoff) => rewrites
+                .iter()
+                .map(|rw| backoff.match_limit(iteration, rw.name))
+                .collect(),
+            None => vec![Some(usize::MAX); rewrites.len()],
+        };
+
+        let mut matches = rewrites
+            .par_iter()
+            .zip(&thresholds)
+            .map(|(rw, threshold)| {
+                let ms = match threshold {
+                    Some(threshold) => limits.timed_search(rw.name, || {
+                        rw.search_with_limit(egraph, threshold.saturating_add(1))
+                    }),
+                    None => vec![],
+                };
+                limits.check_limits(iteration, egraph)?;
+                Ok(ms)
+            })
+            .collect::<RunnerResult<Vec<_>>>()?;
+
+        if let Some(backoff) = &mut self.backoff {
+            for ((rw, threshold), ms) in rewrites.iter().zip(&thresholds).zip(&mut matches) {
+                if let Some(threshold) = *threshold {
+                    let total_len: usize = ms.iter().map(|m| m.substs.len()).sum();
+                    if !backoff.record_matches(iteration, rw.name, threshold, total_len) {
+                        ms.clear();
+                    }
+                }
+            }
+        }
+
+        Ok(matches)
+    }
 }
 
 /// Custom data to inject into the [`Iteration`]s recorded by a [`Runner`]
@@ -988,3 +3167,746 @@ where
 {
     fn make(_: &Runner<L, N, Self>) -> Self {}
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{SymbolLang as S, *};
+
+    fn explosive_rules() -> Vec<Rewrite<S, ()>> {
+        vec![
+            rewrite!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        ]
+    }
+
+    #[test]
+    fn cancel_from_hook() {
+        crate::init_logger();
+        let token = CancelToken::new();
+        let hook_token = token.clone();
+        let runner = Runner::default()
+            .with_expr(
+                &"(+ 1 (+ 2 (+ 3 (+ 4 (+ 5 (+ 6 (+ 7 8)))))))"
+                    .parse()
+                    .unwrap(),
+            )
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(usize::MAX)
+            .with_node_limit(usize::MAX)
+            .with_time_limit(Duration::from_secs(60))
+            .with_cancel_token(token)
+            .with_hook(move |runner| {
+                if runner.iterations.len() == 3 {
+                    hook_token.cancel();
+                }
+                Ok(())
+            })
+            .run(&explosive_rules());
+
+        assert!(matches!(runner.stop_reason, Some(StopReason::Cancelled)));
+        assert_eq!(runner.iterations.len(), 4);
+    }
+
+    #[test]
+    fn cancel_from_other_thread() {
+        crate::init_logger();
+        let token = CancelToken::new();
+        let handle = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                token.cancel();
+            })
+        };
+
+        let start = Instant::now();
+        let runner = Runner::default()
+            .with_expr(
+                &"(+ 1 (+ 2 (+ 3 (+ 4 (+ 5 (+ 6 (+ 7 8)))))))"
+                    .parse()
+                    .unwrap(),
+            )
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(usize::MAX)
+            .with_node_limit(usize::MAX)
+            .with_time_limit(Duration::from_secs(60))
+            .with_cancel_token(token)
+            .run(&explosive_rules());
+        handle.join().unwrap();
+
+        assert!(matches!(runner.stop_reason, Some(StopReason::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn goal_before_egraph() {
+        crate::init_logger();
+        let runner = Runner::default()
+            .with_scheduler(SimpleScheduler)
+            .with_goal(
+                &"(+ 1 (+ 2 3))".parse().unwrap(),
+                &"(+ (+ 3 2) 1)".parse().unwrap(),
+            )
+            .with_egraph(EGraph::default())
+            .run(&explosive_rules());
+        assert!(matches!(runner.stop_reason, Some(StopReason::GoalReached)));
+    }
+
+    #[test]
+    fn pattern_goal() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 (+ 3 4)))".parse().unwrap())
+            .with_scheduler(SimpleScheduler);
+        let root = runner.roots[0];
+        let runner = runner
+            .with_pattern_goal(root, "(+ (+ 4 ?x) ?y)".parse().unwrap())
+            .run(&rules);
+        assert!(matches!(runner.stop_reason, Some(StopReason::GoalReached)));
+        assert!(runner.goal_explanations.is_empty());
+
+        // a goal that can never hold does not stop saturation
+        let runner = Runner::default()
+            .with_goal(&"(+ 1 2)".parse().unwrap(), &"3".parse().unwrap())
+            .run(&rules);
+        assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+    }
+
+    #[test]
+    fn step_and_resume() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let expr = "(+ 1 (+ 2 (+ 3 4)))".parse().unwrap();
+
+        let mut runner = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(2);
+        assert!(runner.step(&rules).is_ok());
+        assert!(runner.stop_reason.is_none());
+        let err = runner.step(&rules).unwrap_err();
+        assert!(matches!(err, StopReason::IterationLimit(2)));
+        // stepping a stopped runner does nothing
+        assert!(runner.step(&rules).is_err());
+        assert_eq!(runner.iterations.len(), 2);
+
+        let stepped = runner.resume().with_iter_limit(usize::MAX).run(&rules);
+        let ran = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(usize::MAX)
+            .run(&rules);
+        assert!(matches!(stepped.stop_reason, Some(StopReason::Saturated)));
+        assert_eq!(stepped.iterations.len(), ran.iterations.len());
+        assert_eq!(stepped.egraph.total_size(), ran.egraph.total_size());
+    }
+
+    #[test]
+    fn resume_extends_limits() {
+        crate::init_logger();
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 (+ 3 (+ 4 5))))".parse().unwrap())
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(2)
+            .run(&explosive_rules());
+        assert!(matches!(
+            runner.stop_reason,
+            Some(StopReason::IterationLimit(2))
+        ));
+
+        let runner = runner.resume().run(&explosive_rules());
+        assert!(matches!(
+            runner.stop_reason,
+            Some(StopReason::IterationLimit(4))
+        ));
+        assert_eq!(runner.iterations.len(), 4);
+    }
+
+    #[cfg(feature = "serde-1")]
+    #[test]
+    fn checkpoint_roundtrip() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let scheduler = || BackoffScheduler::default().with_initial_match_limit(10);
+
+        let mut runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 (+ 3 (+ 4 5))))".parse().unwrap())
+            .with_scheduler(scheduler());
+        for _ in 0..3 {
+            runner.step(&rules).unwrap();
+        }
+
+        let mut file = vec![];
+        runner.save_checkpoint(&mut file).unwrap();
+        let mut restored = Runner::default()
+            .with_scheduler(scheduler())
+            .load_checkpoint(file.as_slice())
+            .unwrap();
+
+        assert_eq!(restored.roots, runner.roots);
+        assert_eq!(restored.iterations.len(), 3);
+        assert_eq!(restored.egraph.total_size(), runner.egraph.total_size());
+        assert_eq!(
+            restored.scheduler.save_state(),
+            runner.scheduler.save_state()
+        );
+
+        runner.step(&rules).unwrap();
+        restored.step(&rules).unwrap();
+        assert_eq!(restored.egraph.total_size(), runner.egraph.total_size());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_scheduler() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let expr = "(+ 1 (+ 2 (+ 3 4)))".parse().unwrap();
+
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .run(&rules);
+        let parallel = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(ParallelScheduler::default())
+            .run(&rules);
+        assert!(matches!(parallel.stop_reason, Some(StopReason::Saturated)));
+        assert_eq!(parallel.iterations.len(), simple.iterations.len());
+        assert_eq!(parallel.egraph.total_size(), simple.egraph.total_size());
+
+        let backoff = || BackoffScheduler::default().with_initial_match_limit(10);
+        let sequential = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(backoff())
+            .run(&rules);
+        let parallel = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(ParallelScheduler::default().with_backoff(backoff()))
+            .run(&rules);
+        assert_eq!(parallel.iterations.len(), sequential.iterations.len());
+        assert_eq!(parallel.egraph.total_size(), sequential.egraph.total_size());
+    }
+
+    #[test]
+    fn bandit_scheduler() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let expr = "(+ 1 (+ 2 (+ 3 4)))".parse().unwrap();
+
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .run(&rules);
+        // a tiny budget forces rules to drop matches, but the runner
+        // should still reach the same saturated egraph
+        let bandit = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(
+                BanditScheduler::default()
+                    .with_match_budget(20)
+                    .with_min_match_limit(5),
+            )
+            .with_iter_limit(1000)
+            .run(&rules);
+        assert!(matches!(bandit.stop_reason, Some(StopReason::Saturated)));
+        assert_eq!(
+            bandit.egraph.number_of_classes(),
+            simple.egraph.number_of_classes()
+        );
+        assert_eq!(
+            bandit.egraph.total_number_of_nodes(),
+            simple.egraph.total_number_of_nodes()
+        );
+    }
+
+    #[test]
+    fn bandit_scheduler_searches_selected_rules() {
+        crate::init_logger();
+        let mut rules = explosive_rules();
+        rules.push(rewrite!("comm-mul"; "(* ?x ?y)" => "(* ?y ?x)"));
+        rules.push(rewrite!("assoc-mul"; "(* ?x (* ?y ?z))" => "(* (* ?x ?y) ?z)"));
+        let expr = "(+ (* 1 (* 2 3)) (+ 4 (* 5 6)))".parse().unwrap();
+
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .run(&rules);
+        // the budget only covers two rules at the minimum limit, so the
+        // others are skipped until they rank higher
+        let bandit = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(
+                BanditScheduler::default()
+                    .with_match_budget(10)
+                    .with_min_match_limit(5),
+            )
+            .with_iter_limit(1000)
+            .run(&rules);
+        assert!(matches!(bandit.stop_reason, Some(StopReason::Saturated)));
+        assert_eq!(
+            bandit.egraph.total_number_of_nodes(),
+            simple.egraph.total_number_of_nodes()
+        );
+
+        let searched: Vec<usize> = bandit
+            .iterations
+            .iter()
+            .map(|it| {
+                let profiles = it.rule_profiles.values();
+                profiles.filter(|p| p.search_time > 0.0).count()
+            })
+            .collect();
+        assert_eq!(searched[0], rules.len());
+        assert!(searched.contains(&2), "{:?}", searched);
+    }
+
+    #[test]
+    fn sampling_scheduler() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let expr = "(+ 1 (+ 2 (+ 3 4)))".parse().unwrap();
+
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .run(&rules);
+
+        let run = |scheduler: SamplingScheduler<S, ()>| {
+            let runner = Runner::default()
+                .with_expr(&expr)
+                .with_scheduler(scheduler.with_initial_match_limit(5))
+                .with_iter_limit(1000)
+                .run(&rules);
+            assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+            assert_eq!(
+                runner.egraph.number_of_classes(),
+                simple.egraph.number_of_classes()
+            );
+            assert_eq!(
+                runner.egraph.total_number_of_nodes(),
+                simple.egraph.total_number_of_nodes()
+            );
+            runner
+        };
+
+        // the same seed gives the same run
+        let a = run(SamplingScheduler::default().with_seed(7));
+        let b = run(SamplingScheduler::default().with_seed(7));
+        let sizes = |r: &Runner<S, ()>| -> Vec<usize> {
+            r.iterations.iter().map(|i| i.egraph_nodes).collect()
+        };
+        assert_eq!(sizes(&a), sizes(&b));
+
+        run(SamplingScheduler::default().with_ranking(|egraph, id, _| egraph[id].len() as f64));
+    }
+
+    #[test]
+    fn incremental_scheduler() {
+        crate::init_logger();
+        let mut rules = explosive_rules();
+        rules.push(multi_rewrite!("pair"; "?a = (+ ?x ?y), ?b = (+ ?y ?x)" => "?a = (pair ?x ?y)"));
+        let expr = "(+ 1 (+ 2 (+ 3 4)))".parse().unwrap();
+
+        // multipatterns always report applications, so the simple
+        // scheduler never sees the egraph saturate
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(20)
+            .run(&rules);
+        let incremental = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(IncrementalScheduler::default())
+            .with_iter_limit(20)
+            .run(&rules);
+
+        assert!(matches!(
+            incremental.stop_reason,
+            Some(StopReason::Saturated)
+        ));
+        assert_eq!(
+            incremental.egraph.number_of_classes(),
+            simple.egraph.number_of_classes()
+        );
+        assert_eq!(
+            incremental.egraph.total_number_of_nodes(),
+            simple.egraph.total_number_of_nodes()
+        );
+
+        let matches = |runner: &Runner<S, ()>| -> usize {
+            let report = runner.report();
+            report.rule_profiles.values().map(|p| p.matches).sum()
+        };
+        assert!(matches(&incremental) < matches(&simple));
+    }
+
+    #[test]
+    fn incremental_scheduler_keeps_dropped_matches() {
+        crate::init_logger();
+        let rules: Vec<Rewrite<S, ()>> = vec![
+            rewrite!("add-comm"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+            rewrite!("mul-comm"; "(* ?a ?b)" => "(* ?b ?a)"),
+        ];
+        let runner = Runner::default()
+            .with_expr(&"(* (+ a b) c)".parse().unwrap())
+            .with_scheduler(IncrementalScheduler::default())
+            .with_after_search_hook(|runner, rules, matches| {
+                // drop the matches of add-comm in the first iteration only
+                if runner.iterations.is_empty() {
+                    for (rule, ms) in rules.iter().zip(matches.iter_mut()) {
+                        if rule.name.as_str() == "add-comm" {
+                            ms.clear();
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .run(&rules);
+
+        let add = runner.egraph.lookup_expr(&"(+ a b)".parse().unwrap());
+        let commuted = runner.egraph.lookup_expr(&"(+ b a)".parse().unwrap());
+        assert!(add.is_some());
+        assert_eq!(add, commuted);
+    }
+
+    #[test]
+    fn shared_scheduler() {
+        crate::init_logger();
+        let mut rules = explosive_rules();
+        rules.push(rewrite!("add-0"; "(+ ?x 0)" => "?x"));
+        rules.push(rewrite!("add-same"; "(+ ?x ?x)" => "(* 2 ?x)"));
+        let expr = "(+ 1 (+ 0 (+ 1 (+ 1 0))))".parse().unwrap();
+
+        let simple = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SimpleScheduler)
+            .run(&rules);
+        let shared = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SharedScheduler::default())
+            .run(&rules);
+
+        assert!(matches!(shared.stop_reason, Some(StopReason::Saturated)));
+        let applied = |r: &Runner<S, ()>| -> Vec<IndexMap<Symbol, usize>> {
+            r.iterations.iter().map(|i| i.applied.clone()).collect()
+        };
+        assert_eq!(applied(&shared), applied(&simple));
+        assert_eq!(
+            shared.egraph.total_number_of_nodes(),
+            simple.egraph.total_number_of_nodes()
+        );
+        let profiles = &shared.report().rule_profiles;
+        assert!(rules.iter().all(|rw| profiles[&rw.name].search_time > 0.0));
+
+        let backoff = || BackoffScheduler::default().with_initial_match_limit(10);
+        let sequential = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(backoff())
+            .run(&rules);
+        let shared = Runner::default()
+            .with_expr(&expr)
+            .with_scheduler(SharedScheduler::default().with_backoff(backoff()))
+            .run(&rules);
+        assert_eq!(applied(&shared), applied(&sequential));
+        let bans = |r: &Runner<S, ()>| -> usize {
+            r.report().rule_profiles.values().map(|p| p.bans).sum()
+        };
+        assert!(bans(&shared) > 0);
+        assert_eq!(bans(&shared), bans(&sequential));
+    }
+
+    #[test]
+    fn run_schedule() {
+        crate::init_logger();
+        let expand: Vec<Rewrite<S, ()>> =
+            vec![rewrite!("distribute"; "(* ?a (+ ?b ?c))" => "(+ (* ?a ?b) (* ?a ?c))")];
+        let simplify: Vec<Rewrite<S, ()>> = vec![
+            rewrite!("mul-1"; "(* ?a 1)" => "?a"),
+            rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+        ];
+        let rule_sets = [("expand", &expand), ("simplify", &simplify)];
+
+        let schedule = "(saturate (seq (saturate expand) (repeat 2 simplify)))"
+            .parse()
+            .unwrap();
+        let runner = Runner::default()
+            .with_expr(&"(* x (+ 1 (* y (+ 1 0))))".parse().unwrap())
+            .run_schedule(&rule_sets, &schedule);
+        assert!(matches!(
+            runner.stop_reason,
+            Some(StopReason::ScheduleFinished)
+        ));
+
+        let phases: Vec<_> = runner.iterations.iter().map(|i| i.phase.unwrap()).collect();
+        assert_eq!(phases[0], "expand".into());
+        assert!(phases.contains(&"simplify".into()));
+        assert!(runner.iterations.iter().all(|i| i.stop_reason.is_none()));
+
+        let x_plus_xy = runner.egraph.lookup_expr(&"(+ x (* x y))".parse().unwrap());
+        assert_eq!(x_plus_xy, Some(runner.egraph.find(runner.roots[0])));
+
+        // limits still apply across phases
+        let runner = Runner::default()
+            .with_expr(&"(* x (+ 1 (* y (+ 1 0))))".parse().unwrap())
+            .with_iter_limit(2)
+            .run_schedule(&rule_sets, &schedule);
+        assert!(matches!(
+            runner.stop_reason,
+            Some(StopReason::IterationLimit(2))
+        ));
+    }
+
+    #[test]
+    fn rule_profiles() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 (+ 3 (+ 4 5))))".parse().unwrap())
+            .with_scheduler(BackoffScheduler::default().with_initial_match_limit(10))
+            .run(&rules);
+
+        for iteration in &runner.iterations {
+            assert_eq!(iteration.rule_profiles.len(), rules.len());
+            for (name, profile) in &iteration.rule_profiles {
+                let applied = iteration.applied.get(name).copied().unwrap_or(0);
+                assert_eq!(profile.applied, applied);
+                assert!(profile.applied <= profile.matches);
+            }
+        }
+
+        let report = runner.report();
+        let assoc = &report.rule_profiles[&Symbol::from("assoc")];
+        assert!(assoc.bans > 0);
+        assert!(assoc.search_time > 0.0);
+        let applied: usize = report.rule_profiles.values().map(|p| p.applied).sum();
+        let expected: usize = runner
+            .iterations
+            .iter()
+            .flat_map(|i| i.applied.values())
+            .sum();
+        assert_eq!(applied, expected);
+        assert!(report.to_string().contains("Top rules by time"));
+    }
+
+    #[test]
+    fn lifecycle_hooks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        crate::init_logger();
+        let events = Rc::new(RefCell::new(vec![]));
+        let log = |name: &'static str| {
+            let events = events.clone();
+            move || -> RunnerResult<()> {
+                events.borrow_mut().push(name.to_owned());
+                Ok(())
+            }
+        };
+
+        let (before, after_search, after_apply, after_rebuild) =
+            (log("before"), log("search"), log("apply"), log("rebuild"));
+        let on_stop = events.clone();
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 (+ 3 4)))".parse().unwrap())
+            .with_before_search_hook(move |_| before())
+            .with_after_search_hook(move |_, rules, matches| {
+                assert_eq!(rules.len(), matches.len());
+                after_search()
+            })
+            .with_after_apply_hook(move |_, _| after_apply())
+            .with_after_rebuild_hook(move |runner| {
+                after_rebuild()?;
+                assert!(runner.egraph.clean);
+                if runner.iterations.len() == 1 {
+                    Err(StopReason::Other("enough".into()))
+                } else {
+                    Ok(())
+                }
+            })
+            .with_on_stop_hook(move |_, reason| on_stop.borrow_mut().push(format!("{reason:?}")))
+            .run(&explosive_rules());
+
+        assert!(matches!(&runner.stop_reason, Some(StopReason::Other(s)) if s == "enough"));
+        assert_eq!(runner.iterations.len(), 2);
+        assert_eq!(
+            *events.borrow(),
+            [
+                "before",
+                "search",
+                "apply",
+                "rebuild", //
+                "before",
+                "search",
+                "apply",
+                "rebuild", //
+                "Other(\"enough\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn beam_pruning() {
+        crate::init_logger();
+        let mut rules = explosive_rules();
+        rules.push(rewrite!("add-0"; "(+ ?x 0)" => "?x"));
+        let expr = "(+ 1 (+ (+ 2 0) (+ 3 (+ 4 0))))".parse().unwrap();
+        let run = |runner: Runner<S, ()>| {
+            runner
+                .with_expr(&expr)
+                .with_scheduler(SimpleScheduler)
+                .with_iter_limit(4)
+                .run(&rules)
+        };
+
+        let full = run(Runner::default());
+        let pruned = run(Runner::default().with_beam_pruning(AstSize, 1.0));
+        assert!(pruned.iterations.iter().any(|i| i.n_pruned > 0));
+        for class in pruned.egraph.classes() {
+            assert!(!class.is_empty());
+        }
+        assert!(
+            pruned.egraph.total_number_of_nodes() < full.egraph.total_number_of_nodes(),
+            "pruning did not shrink the egraph"
+        );
+
+        let best = |runner: &Runner<S, ()>| {
+            Extractor::new(&runner.egraph, AstSize)
+                .find_best(runner.roots[0])
+                .0
+        };
+        assert_eq!(best(&pruned), best(&full));
+    }
+
+    #[test]
+    fn depth_limit() {
+        crate::init_logger();
+        let rules = explosive_rules();
+        let run = |depth| {
+            Runner::default()
+                .with_expr(&"(f (+ 1 2) (g (+ 3 (+ 4 5))))".parse().unwrap())
+                .with_depth_limit(depth)
+                .run(&rules)
+        };
+        let has = |runner: &Runner<S, ()>, expr: &str| {
+            runner.egraph.lookup_expr(&expr.parse().unwrap()).is_some()
+        };
+
+        let shallow = run(1);
+        assert!(matches!(shallow.stop_reason, Some(StopReason::Saturated)));
+        assert!(has(&shallow, "(+ 2 1)"));
+        assert!(!has(&shallow, "(+ (+ 3 4) 5)"));
+        assert!(!has(&shallow, "(+ 5 4)"));
+
+        let deeper = run(2);
+        assert!(has(&deeper, "(+ (+ 3 4) 5)"));
+        assert!(!has(&deeper, "(+ 5 4)"));
+        assert!(has(&run(3), "(+ 5 4)"));
+
+        // searching a rule from a hook is not limited to the scope
+        let runner = Runner::default()
+            .with_expr(&"(f (+ 1 2) (g (+ 3 (+ 4 5))))".parse().unwrap())
+            .with_depth_limit(1)
+            .with_iter_limit(1)
+            .with_after_search_hook(|runner, rules, matches| {
+                let scoped: usize = matches[0].iter().map(|m| m.substs.len()).sum();
+                let all: usize = rules[0]
+                    .search(&runner.egraph)
+                    .iter()
+                    .map(|m| m.substs.len())
+                    .sum();
+                assert_eq!((scoped, all), (1, 3));
+                Ok(())
+            })
+            .run(&rules);
+        assert_eq!(runner.iterations.len(), 1);
+    }
+
+    #[cfg(feature = "reports")]
+    #[test]
+    fn event_log() {
+        use std::io::{self, Write};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        crate::init_logger();
+        let buf = Shared::default();
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 3))".parse().unwrap())
+            .with_event_log(buf.clone())
+            .run(&explosive_rules());
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let count = |kind: &str| events.iter().filter(|e| e["event"] == kind).count();
+
+        assert_eq!(count("iteration"), runner.iterations.len());
+        assert_eq!(count("rebuild"), runner.iterations.len());
+        let matches: usize = runner
+            .report()
+            .rule_profiles
+            .values()
+            .map(|p| p.matches)
+            .sum();
+        assert_eq!(count("apply"), matches);
+        assert!(count("union") > 0);
+
+        let apply = events.iter().find(|e| e["event"] == "apply").unwrap();
+        assert_eq!(apply["iteration"], 0);
+        assert!(apply["subst"]["?x"].is_u64());
+        assert_eq!(events.last().unwrap()["stop_reason"], "Saturated");
+
+        // a runner without an event log, run from a hook of one with a
+        // log, does not log its unions there
+        let nested = Shared::default();
+        Runner::default()
+            .with_expr(&"(+ 1 (+ 2 3))".parse().unwrap())
+            .with_event_log(nested.clone())
+            .with_hook(|_| {
+                Runner::<S, ()>::default()
+                    .with_expr(&"(+ 4 (+ 5 6))".parse().unwrap())
+                    .run(&explosive_rules());
+                Ok(())
+            })
+            .run(&explosive_rules());
+        let nested = String::from_utf8(nested.0.lock().unwrap().clone()).unwrap();
+        let n_unions = |text: &str| text.matches(r#""event":"union""#).count();
+        assert_eq!(n_unions(&nested), n_unions(&text));
+
+        let mut subst = Subst::default();
+        subst.insert("?a".parse().unwrap(), Id::from(1));
+        subst.insert_leaf("?n".parse().unwrap(), Id::from(2));
+        let json = serde_json::to_value(SubstJson(&subst)).unwrap();
+        assert_eq!(json, serde_json::json!({"?a": 1, "?n": 2}));
+
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let runner = Runner::default()
+            .with_expr(&"(+ 1 (+ 2 3))".parse().unwrap())
+            .with_event_log(Broken)
+            .run(&explosive_rules());
+        assert!(matches!(&runner.stop_reason, Some(StopReason::Other(s)) if s.contains("broken")));
+        assert_eq!(runner.iterations.len(), 1);
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use symbolic_expressions::{Sexp, SexpError};
+use thiserror::Error;
+
+use crate::Symbol;
+
+/** A schedule of named rule sets, run by
+[`Runner::run_schedule`](crate::Runner::run_schedule()).
+
+A [`Schedule`] says which rule set to run when.
+The leaves of a schedule are the names of rule sets; running one of them
+performs a single [`Runner`](crate::Runner) iteration with just those
+rules.
+These are combined with:
+
+- [`Seq`](Schedule::Seq): run some schedules one after the other.
+- [`Repeat`](Schedule::Repeat): run a schedule some number of times.
+- [`Saturate`](Schedule::Saturate): run a schedule until it stops
+  changing the [`EGraph`](crate::EGraph).
+
+Schedules can be built with the constructor methods or parsed from an
+s-expression:
+
+```
+# use egg::*;
+// saturate expand, then run simplify 3 times, repeat until no change
+let built = Schedule::run("expand")
+    .saturate()
+    .then(Schedule::run("simplify").repeat(3))
+    .saturate();
+let parsed: Schedule = "(saturate (seq (saturate expand) (repeat 3 simplify)))"
+    .parse()
+    .unwrap();
+assert_eq!(built, parsed);
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Run one iteration with the rule set of this name.
+    Run(Symbol),
+    /// Run each schedule in order.
+    Seq(Vec<Schedule>),
+    /// Run the schedule this many times, stopping early if an entire
+    /// run of it did not change the egraph.
+    Repeat(usize, Box<Schedule>),
+    /// Run the schedule until an entire run of it does not change the
+    /// egraph.
+    Saturate(Box<Schedule>),
+}
+
+impl Schedule {
+    /// A schedule that runs the named rule set once.
+    pub fn run(name: impl Into<Symbol>) -> Self {
+        Schedule::Run(name.into())
+    }
+
+    /// Run `self`, then `next`.
+    pub fn then(self, next: Schedule) -> Self {
+        match self {
+            Schedule::Seq(mut schedules) => {
+                schedules.push(next);
+                Schedule::Seq(schedules)
+            }
+            first => Schedule::Seq(vec![first, next]),
+        }
+    }
+
+    /// Run `self` up to `n` times.
+    pub fn repeat(self, n: usize) -> Self {
+        Schedule::Repeat(n, Box::new(self))
+    }
+
+    /// Run `self` until it stops changing the egraph.
+    pub fn saturate(self) -> Self {
+        Schedule::Saturate(Box::new(self))
+    }
+
+    /// Call `f` on the name of each rule set this schedule mentions.
+    pub fn for_each_rule_set(&self, f: &mut impl FnMut(Symbol)) {
+        match self {
+            Schedule::Run(name) => f(*name),
+            Schedule::Seq(schedules) => schedules.iter().for_each(|s| s.for_each_rule_set(f)),
+            Schedule::Repeat(_, s) | Schedule::Saturate(s) => s.for_each_rule_set(f),
+        }
+    }
+
+    fn to_sexp(&self) -> Sexp {
+        let s = |s: &str| Sexp::String(s.to_owned());
+        match self {
+            Schedule::Run(name) => s(name.as_str()),
+            Schedule::Seq(schedules) => {
+                let mut list = vec![s("seq")];
+                list.extend(schedules.iter().map(|s| s.to_sexp()));
+                Sexp::List(list)
+            }
+            Schedule::Repeat(n, sched) => {
+                Sexp::List(vec![s("repeat"), s(&n.to_string()), sched.to_sexp()])
+            }
+            Schedule::Saturate(sched) => Sexp::List(vec![s("saturate"), sched.to_sexp()]),
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_sexp())
+    }
+}
+
+/// An error type for failures when attempting to parse a [`Schedule`].
+#[derive(Debug, Error)]
+pub enum ScheduleParseError {
+    /// An empty s-expression was found.
+    #[error("found empty s-expression")]
+    EmptySexp,
+
+    /// A list didn't start with `seq`, `repeat`, or `saturate`, or had
+    /// the wrong arguments for it.
+    #[error("expected (seq ..), (repeat n ..), or (saturate ..), found: {0}")]
+    BadList(Sexp),
+
+    /// An error occurred while parsing the s-expression itself.
+    #[error(transparent)]
+    BadSexp(SexpError),
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ScheduleParseError::*;
+
+        fn parse(sexp: &Sexp) -> Result<Schedule, ScheduleParseError> {
+            let list = match sexp {
+                Sexp::Empty => return Err(EmptySexp),
+                Sexp::String(name) => return Ok(Schedule::run(name.as_str())),
+                Sexp::List(list) => list,
+            };
+            let bad = || BadList(sexp.clone());
+            match list.as_slice() {
+                [Sexp::String(head), rest @ ..] if head == "seq" => rest
+                    .iter()
+                    .map(parse)
+                    .collect::<Result<_, _>>()
+                    .map(Schedule::Seq),
+                [Sexp::String(head), Sexp::String(n), sched] if head == "repeat" => {
+                    let n = n.parse().map_err(|_| bad())?;
+                    Ok(parse(sched)?.repeat(n))
+                }
+                [Sexp::String(head), sched] if head == "saturate" => Ok(parse(sched)?.saturate()),
+                _ => Err(bad()),
+            }
+        }
+
+        let sexp = symbolic_expressions::parser::parse_str(s.trim()).map_err(BadSexp)?;
+        parse(&sexp)
+    }
+}
@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::fmt;
 use std::str::FromStr;
 
@@ -96,9 +97,16 @@ impl From<u32> for Var {
 
 /// A substitution mapping [`Var`]s to eclass [`Id`]s.
 ///
+/// Sequence variables (written `?xs...` in a [`Pattern`]) are mapped to
+/// lists of eclass [`Id`]s separately; see [`get_seq`](Subst::get_seq()).
+/// Leaf variables (written `?n!Num`) are also mapped to the leaf they
+/// matched, whose data can be read with [`leaf`](Subst::leaf()).
+///
 #[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Subst {
     pub(crate) vec: smallvec::SmallVec<[(Var, Id); 3]>,
+    pub(crate) seqs: Vec<(Var, Vec<Id>)>,
+    pub(crate) leaves: Vec<(Var, Id)>,
 }
 
 impl Subst {
@@ -106,6 +114,8 @@ impl Subst {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             vec: smallvec::SmallVec::with_capacity(capacity),
+            seqs: vec![],
+            leaves: vec![],
         }
     }
 
@@ -127,6 +137,77 @@ impl Subst {
             .iter()
             .find_map(|(v, id)| if *v == var { Some(id) } else { None })
     }
+
+    /// Insert a sequence variable, returning the old `Id`s if present.
+    pub fn insert_seq(&mut self, var: Var, ids: Vec<Id>) -> Option<Vec<Id>> {
+        for pair in &mut self.seqs {
+            if pair.0 == var {
+                return Some(std::mem::replace(&mut pair.1, ids));
+            }
+        }
+        self.seqs.push((var, ids));
+        None
+    }
+
+    /// Retrieve a sequence variable, returning `None` if not present.
+    pub fn get_seq(&self, var: Var) -> Option<&[Id]> {
+        self.seqs
+            .iter()
+            .find_map(|(v, ids)| if *v == var { Some(&ids[..]) } else { None })
+    }
+
+    /// Insert the childless enode a leaf variable matched, given by its
+    /// enode `Id` (see [`EGraph::id_to_node`]), returning the old one if
+    /// present.
+    pub fn insert_leaf(&mut self, var: Var, node: Id) -> Option<Id> {
+        for pair in &mut self.leaves {
+            if pair.0 == var {
+                return Some(std::mem::replace(&mut pair.1, node));
+            }
+        }
+        self.leaves.push((var, node));
+        None
+    }
+
+    /// Retrieve the enode `Id` of the childless enode a leaf variable
+    /// matched, returning `None` if not present.
+    pub fn get_leaf(&self, var: Var) -> Option<Id> {
+        self.leaves
+            .iter()
+            .find_map(|(v, id)| if *v == var { Some(*id) } else { None })
+    }
+
+    /// Retrieve the data of the leaf a leaf variable matched, like the `3`
+    /// in the `Num(3)` that `?n!Num` matched, returning `None` if not
+    /// present or if the data is not a `T`.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// define_language! {
+    ///     enum Math {
+    ///         Num(i32),
+    ///         "+" = Add([Id; 2]),
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<Math, ()>::default();
+    /// egraph.add_expr(&"(+ 1 2)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let pattern: Pattern<Math> = "(+ ?a!Num ?b!Num)".parse().unwrap();
+    /// let matches = pattern.search(&egraph);
+    /// let subst = &matches[0].substs[0];
+    /// let num = |v: &str| *subst.leaf::<i32, _, _>(&egraph, v.parse().unwrap()).unwrap();
+    /// assert_eq!(num("?a") + num("?b"), 3);
+    /// ```
+    pub fn leaf<'a, T: Any, L: Language + 'static, N: Analysis<L>>(
+        &self,
+        egraph: &'a EGraph<L, N>,
+        var: Var,
+    ) -> Option<&'a T> {
+        let id = self.get_leaf(var)?;
+        egraph.id_to_node(id).leaf_data()?.downcast_ref()
+    }
 }
 
 impl std::ops::Index<Var> for Subst {
@@ -151,6 +232,18 @@ impl Debug for Subst {
                 write!(f, ", ")?;
             }
         }
+        for (i, (var, ids)) in self.seqs.iter().enumerate() {
+            if len > 0 || i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}...: {:?}", var, ids)?;
+        }
+        for (i, (var, id)) in self.leaves.iter().enumerate() {
+            if len + self.seqs.len() > 0 || i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}!: {}", var, id)?;
+        }
         write!(f, "}}")
     }
 }
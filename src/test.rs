@@ -64,17 +64,9 @@ pub fn test_runner<L, A>(
     let id = runner.egraph.find(*runner.roots.last().unwrap());
 
     if check_fn.is_none() {
-        let goals = goals.to_vec();
-        runner = runner.with_hook(move |r| {
-            if goals
-                .iter()
-                .all(|g: &Pattern<_>| g.search_eclass(&r.egraph, id).is_some())
-            {
-                Err("Proved all goals".into())
-            } else {
-                Ok(())
-            }
-        });
+        for goal in goals {
+            runner = runner.with_pattern_goal(id, goal.clone());
+        }
     }
     let mut runner = runner.run(rules);
 
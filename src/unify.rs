@@ -0,0 +1,543 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::util::{HashMap, IndexMap};
+use crate::*;
+
+/// The variable of a pattern node, if it is one.
+///
+/// Guards and leaf bindings only constrain what a variable matches in an
+/// e-graph, so they are ignored here.
+fn var_of<L>(node: &ENodeOrVar<L>) -> Option<Var> {
+    match node {
+        ENodeOrVar::ENode(_) => None,
+        ENodeOrVar::Var(v) | ENodeOrVar::Guarded(v, _) | ENodeOrVar::Leaf(v, _) => Some(*v),
+        ENodeOrVar::Seq(_) => unreachable!("sequence vars are checked first"),
+    }
+}
+
+fn check_no_seqs<L>(pat: &PatternAst<L>) {
+    for node in pat.as_ref() {
+        if let ENodeOrVar::Seq(v) = node {
+            panic!("Unification does not support sequence var {}", v);
+        }
+    }
+}
+
+/// Copies the term rooted at `id` in `pat` into `out`, calling `f` first
+/// on each node to give it a chance to replace that subterm.
+fn copy_term<L: Language>(
+    pat: &PatternAst<L>,
+    id: Id,
+    out: &mut PatternAst<L>,
+    f: &mut impl FnMut(Id, &ENodeOrVar<L>, &mut PatternAst<L>) -> Option<Id>,
+) -> Id {
+    let node = &pat[id];
+    if let Some(new) = f(id, node, out) {
+        return new;
+    }
+    let node = node.clone().map_children(|c| copy_term(pat, c, out, f));
+    out.add(node)
+}
+
+/// Two patterns side by side, with the variable bindings found so far.
+struct Unification<L> {
+    nodes: Vec<ENodeOrVar<L>>,
+    bindings: IndexMap<Var, Id>,
+}
+
+impl<L: Language> Unification<L> {
+    fn new(a: &PatternAst<L>, b: &PatternAst<L>) -> (Self, Id, Id) {
+        let offset = a.len();
+        let mut nodes = a.as_ref().to_vec();
+        nodes.extend(b.as_ref().iter().map(|node| {
+            node.clone()
+                .map_children(|c| Id::from(usize::from(c) + offset))
+        }));
+        let this = Unification {
+            nodes,
+            bindings: Default::default(),
+        };
+        (this, a.root(), Id::from(usize::from(b.root()) + offset))
+    }
+
+    fn node(&self, id: Id) -> &ENodeOrVar<L> {
+        &self.nodes[usize::from(id)]
+    }
+
+    /// Follows the bindings of variables from `id`.
+    fn walk(&self, mut id: Id) -> Id {
+        while let Some(&bound) = var_of(self.node(id)).and_then(|v| self.bindings.get(&v)) {
+            id = bound;
+        }
+        id
+    }
+
+    fn occurs(&self, v: Var, id: Id) -> bool {
+        match self.node(self.walk(id)) {
+            ENodeOrVar::ENode(n) => n.children().iter().any(|&c| self.occurs(v, c)),
+            node => var_of(node) == Some(v),
+        }
+    }
+
+    fn unify(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.walk(a), self.walk(b));
+        if a == b {
+            return true;
+        }
+        match (self.node(a), self.node(b)) {
+            (ENodeOrVar::ENode(x), ENodeOrVar::ENode(y)) => {
+                if !x.matches(y) {
+                    return false;
+                }
+                let pairs: Vec<(Id, Id)> = x
+                    .children()
+                    .iter()
+                    .copied()
+                    .zip(y.children().iter().copied())
+                    .collect();
+                pairs.into_iter().all(|(c, d)| self.unify(c, d))
+            }
+            (x, y) => match (var_of(x), var_of(y)) {
+                (Some(v), Some(w)) if v == w => true,
+                (Some(v), _) => self.bind(v, b),
+                (None, Some(w)) => self.bind(w, a),
+                (None, None) => unreachable!(),
+            },
+        }
+    }
+
+    fn bind(&mut self, v: Var, id: Id) -> bool {
+        if self.occurs(v, id) {
+            false
+        } else {
+            self.bindings.insert(v, id);
+            true
+        }
+    }
+
+    /// Adds the term at `id` to `out` with all bound variables replaced.
+    fn resolve(&self, id: Id, out: &mut PatternAst<L>) -> Id {
+        let id = self.walk(id);
+        let node = self.node(id).clone().map_children(|c| self.resolve(c, out));
+        out.add(node)
+    }
+
+    fn finish(self) -> Unifier<L> {
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|(&v, &id)| {
+                let mut pat = PatternAst::default();
+                self.resolve(id, &mut pat);
+                (v, pat)
+            })
+            .collect();
+        Unifier { bindings }
+    }
+}
+
+/// A substitution of patterns for pattern variables, like the most
+/// general unifier computed by [`PatternAst::unify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unifier<L> {
+    bindings: Vec<(Var, PatternAst<L>)>,
+}
+
+impl<L: Language> Unifier<L> {
+    /// Returns the pattern `v` is replaced with, if any.
+    pub fn get(&self, v: Var) -> Option<&PatternAst<L>> {
+        self.bindings
+            .iter()
+            .find_map(|(v2, pat)| if *v2 == v { Some(pat) } else { None })
+    }
+
+    /// Iterates over the variables this replaces and their replacements.
+    pub fn iter(&self) -> impl Iterator<Item = (Var, &PatternAst<L>)> {
+        self.bindings.iter().map(|(v, pat)| (*v, pat))
+    }
+
+    /// Returns `pat` with the variables this binds replaced.
+    pub fn apply(&self, pat: &PatternAst<L>) -> PatternAst<L> {
+        let mut out = PatternAst::default();
+        copy_term(pat, pat.root(), &mut out, &mut |_, node, out| {
+            let bound = self.get(var_of(node)?)?;
+            Some(copy_term(bound, bound.root(), out, &mut |_, _, _| None))
+        });
+        out
+    }
+}
+
+impl<L: Language> PatternAst<L> {
+    /// Computes the most general unifier of two patterns: the
+    /// substitution with the fewest commitments that makes them equal.
+    ///
+    /// Returns `None` if no substitution makes them equal.
+    /// Variables with the same name in both patterns are the same
+    /// variable, so rename one apart first if that is not what you want.
+    /// Guards and leaf variables (like `?x:guard` and `?n!Num`) are treated
+    /// like plain variables, so this may unify patterns that never match
+    /// the same e-class.
+    ///
+    /// This panics if either pattern has a sequence variable.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let a: PatternAst<SymbolLang> = "(+ ?a (* ?b 2))".parse().unwrap();
+    /// let b: PatternAst<SymbolLang> = "(+ (* ?c ?c) ?d)".parse().unwrap();
+    /// let unifier = a.unify(&b).unwrap();
+    /// let unified = unifier.apply(&a);
+    /// assert_eq!(unified, unifier.apply(&b));
+    /// assert_eq!(unified.to_string(), "(+ (* ?c ?c) (* ?b 2))");
+    ///
+    /// let c: PatternAst<SymbolLang> = "(+ ?a (/ ?b 2))".parse().unwrap();
+    /// assert!(a.unify(&c).is_none());
+    /// ```
+    pub fn unify(&self, other: &Self) -> Option<Unifier<L>> {
+        check_no_seqs(self);
+        check_no_seqs(other);
+        let (mut unification, a, b) = Unification::new(self, other);
+        if unification.unify(a, b) {
+            Some(unification.finish())
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if this pattern is at least as general as `other`,
+    /// so it matches everywhere `other` does.
+    ///
+    /// This holds when substituting for the variables of this pattern
+    /// gives `other`, whose variables are treated as constants.
+    /// A guarded variable only subsumes a variable with the same guard,
+    /// and a leaf variable only subsumes a leaf variable or a leaf.
+    ///
+    /// This panics if either pattern has a sequence variable.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let general: PatternAst<SymbolLang> = "(+ ?a ?b)".parse().unwrap();
+    /// let specific: PatternAst<SymbolLang> = "(+ (* ?x 2) ?x)".parse().unwrap();
+    /// assert!(general.subsumes(&specific));
+    /// assert!(!specific.subsumes(&general));
+    /// ```
+    pub fn subsumes(&self, other: &Self) -> bool {
+        subsumes_all(&[self], &[other])
+    }
+}
+
+/// Returns `true` if one substitution for the variables of the `general`
+/// patterns turns each of them into the `specific` pattern at the same
+/// index, like [`PatternAst::subsumes`] for several patterns at once.
+pub(crate) fn subsumes_all<L: Language>(
+    general: &[&PatternAst<L>],
+    specific: &[&PatternAst<L>],
+) -> bool {
+    general
+        .iter()
+        .chain(specific)
+        .for_each(|pat| check_no_seqs(pat));
+    let mut matcher = Subsumption {
+        specific,
+        bindings: Default::default(),
+    };
+    general.len() == specific.len()
+        && general
+            .iter()
+            .zip(specific)
+            .enumerate()
+            .all(|(i, (g, s))| matcher.subsumes(g, g.root(), i, s.root()))
+}
+
+/// Matches general patterns against specific ones, binding the variables
+/// of the general patterns to subterms of the specific ones.
+struct Subsumption<'a, L> {
+    specific: &'a [&'a PatternAst<L>],
+    bindings: HashMap<Var, (usize, Id)>,
+}
+
+impl<L: Language> Subsumption<'_, L> {
+    fn subsumes(&mut self, general: &PatternAst<L>, g: Id, i: usize, s: Id) -> bool {
+        let specific = self.specific[i];
+        let v = match (&general[g], &specific[s]) {
+            (ENodeOrVar::ENode(x), ENodeOrVar::ENode(y)) => {
+                return x.matches(y)
+                    && x.children()
+                        .iter()
+                        .zip(y.children())
+                        .all(|(&c, &d)| self.subsumes(general, c, i, d));
+            }
+            (ENodeOrVar::Var(v), _) => v,
+            (ENodeOrVar::Guarded(v, guard), ENodeOrVar::Guarded(_, other)) if guard == other => v,
+            (ENodeOrVar::Leaf(v, variant), ENodeOrVar::Leaf(_, other)) if variant == other => v,
+            (ENodeOrVar::Leaf(v, variant), ENodeOrVar::ENode(n))
+                if n.is_leaf() && n.leaf_variant() == Some(variant.as_str()) =>
+            {
+                v
+            }
+            _ => return false,
+        };
+        match self.bindings.get(v) {
+            Some(&(j, bound)) => self.same_term(j, bound, i, s),
+            None => {
+                self.bindings.insert(*v, (i, s));
+                true
+            }
+        }
+    }
+
+    fn same_term(&self, i: usize, a: Id, j: usize, b: Id) -> bool {
+        match (&self.specific[i][a], &self.specific[j][b]) {
+            (ENodeOrVar::ENode(x), ENodeOrVar::ENode(y)) => {
+                x.matches(y)
+                    && x.children()
+                        .iter()
+                        .zip(y.children())
+                        .all(|(&c, &d)| self.same_term(i, c, j, d))
+            }
+            (x, y) => x == y,
+        }
+    }
+}
+
+/// Renames the variables of `pats` apart from those of `avoid`, by adding
+/// primes, keeping the variables shared between `pats` the same.
+pub(crate) fn rename_apart<L: Language>(
+    pats: &[&PatternAst<L>],
+    avoid: &[&PatternAst<L>],
+) -> Vec<PatternAst<L>> {
+    let vars = |pats: &[&PatternAst<L>]| -> Vec<Var> {
+        pats.iter()
+            .flat_map(|pat| pat.as_ref().iter().filter_map(var_of))
+            .collect()
+    };
+    let mut taken = vars(pats);
+    taken.extend(vars(avoid));
+    let mut renames = HashMap::<Var, Var>::default();
+    for v in vars(pats) {
+        if !renames.contains_key(&v) {
+            let mut name = format!("{}'", v);
+            while taken.iter().any(|t| t.to_string() == name) {
+                name.push('\'');
+            }
+            let renamed: Var = name.parse().unwrap();
+            taken.push(renamed);
+            renames.insert(v, renamed);
+        }
+    }
+
+    let rename = |pat: &PatternAst<L>| {
+        let mut out = PatternAst::default();
+        copy_term(pat, pat.root(), &mut out, &mut |_, node, out| {
+            let renamed = renames[&var_of(node)?];
+            let node = match node {
+                ENodeOrVar::Guarded(_, guard) => ENodeOrVar::Guarded(renamed, *guard),
+                ENodeOrVar::Leaf(_, variant) => ENodeOrVar::Leaf(renamed, *variant),
+                _ => ENodeOrVar::Var(renamed),
+            };
+            Some(out.add(node))
+        });
+        out
+    };
+    pats.iter().map(|pat| rename(pat)).collect()
+}
+
+/// An overlap between two rewrites, found by
+/// [`Rewrite::critical_pairs`].
+///
+/// Both rewrites apply to `term`, one giving `left` and the other
+/// giving `right`.
+/// If the rule set is confluent, `left` and `right` can be rewritten to
+/// the same term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPair<L> {
+    /// The most general term both rewrites apply to.
+    pub term: PatternAst<L>,
+    /// The result of applying the first rewrite at the root of `term`.
+    pub left: PatternAst<L>,
+    /// The result of applying the second rewrite inside `term`.
+    pub right: PatternAst<L>,
+}
+
+impl<L: Language + Display> Display for CriticalPair<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {} / {}", self.term, self.left, self.right)
+    }
+}
+
+impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
+    /// Computes the critical pairs of this rewrite with `other`: the
+    /// most general terms where `other` applies to a subterm of this
+    /// rewrite's left-hand side that is not a variable, and the two
+    /// ways of rewriting them.
+    ///
+    /// The variables of `other` are renamed apart first, with primes.
+    /// Pairs whose two sides are the same are left out, including the
+    /// overlap of a rewrite with itself at the root.
+    /// Conditions are ignored, and this returns nothing if either side
+    /// of either rewrite is not a pattern.
+    ///
+    /// This panics if a pattern has a sequence variable.
+    ///
+    /// ```
+    /// use egg::{rewrite as rw, *};
+    /// let assoc: Rewrite<SymbolLang, ()> = rw!("assoc"; "(+ (+ ?a ?b) ?c)" => "(+ ?a (+ ?b ?c))");
+    /// let zero: Rewrite<SymbolLang, ()> = rw!("zero"; "(+ 0 ?a)" => "?a");
+    /// let pairs = assoc.critical_pairs(&zero);
+    /// assert_eq!(pairs.len(), 1);
+    /// assert_eq!(pairs[0].to_string(), "(+ (+ 0 ?a') ?c) => (+ 0 (+ ?a' ?c)) / (+ ?a' ?c)");
+    /// ```
+    pub fn critical_pairs(&self, other: &Self) -> Vec<CriticalPair<L>> {
+        let ((l1, r1), (l2, r2)) = match (self.pattern_sides(), other.pattern_sides()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return vec![],
+        };
+
+        let renamed = rename_apart(&[l2, r2], &[l1, r1]);
+        let (l2, r2) = (&renamed[0], &renamed[1]);
+
+        // the non-variable subterms of l1, in the order copy_term visits them
+        let mut positions = vec![];
+        copy_term(
+            l1,
+            l1.root(),
+            &mut PatternAst::default(),
+            &mut |id, node, _| {
+                if let ENodeOrVar::ENode(_) = node {
+                    positions.push(id);
+                }
+                None
+            },
+        );
+
+        let mut pairs = vec![];
+        for (position, &id) in positions.iter().enumerate() {
+            if position == 0 && self.name == other.name {
+                continue;
+            }
+            let unifier = match l1.extract(id).unify(l2) {
+                Some(unifier) => unifier,
+                None => continue,
+            };
+            let mut seen = 0;
+            let mut replaced = PatternAst::default();
+            copy_term(l1, l1.root(), &mut replaced, &mut |_, node, out| {
+                if let ENodeOrVar::ENode(_) = node {
+                    seen += 1;
+                    if seen - 1 == position {
+                        return Some(copy_term(r2, r2.root(), out, &mut |_, _, _| None));
+                    }
+                }
+                None
+            });
+
+            let left = unifier.apply(r1);
+            let right = unifier.apply(&replaced);
+            if left != right {
+                pairs.push(CriticalPair {
+                    term: unifier.apply(l1),
+                    left,
+                    right,
+                });
+            }
+        }
+        pairs
+    }
+
+    fn pattern_sides(&self) -> Option<(&PatternAst<L>, &PatternAst<L>)> {
+        Some((
+            self.searcher.get_pattern_ast()?,
+            self.applier.get_pattern_ast()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rewrite as rw, SymbolLang as S, *};
+
+    fn pat(s: &str) -> PatternAst<S> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn unify() {
+        let unify = |a: &str, b: &str| {
+            let (a, b) = (pat(a), pat(b));
+            let unifier = a.unify(&b)?;
+            let unified = unifier.apply(&a);
+            assert_eq!(unified, unifier.apply(&b));
+            Some(unified.to_string())
+        };
+        assert_eq!(unify("(f ?x ?y)", "(f a ?x)").unwrap(), "(f a a)");
+        assert_eq!(
+            unify("(f ?x ?x)", "(f ?y (g ?z))").unwrap(),
+            "(f (g ?z) (g ?z))"
+        );
+        assert_eq!(unify("?x", "?x").unwrap(), "?x");
+        assert_eq!(unify("(f ?x ?x)", "(f a b)"), None);
+        assert_eq!(unify("(f ?x)", "(f a b)"), None);
+        // occurs check
+        assert_eq!(unify("(f ?x ?x)", "(f ?y (g ?y))"), None);
+    }
+
+    #[test]
+    fn subsumes() {
+        let subsumes = |a: &str, b: &str| pat(a).subsumes(&pat(b));
+        assert!(subsumes("?x", "(f a)"));
+        assert!(subsumes("(f ?x ?x)", "(f (g ?y) (g ?y))"));
+        assert!(!subsumes("(f ?x ?x)", "(f (g ?y) (g ?z))"));
+        assert!(!subsumes("(f a)", "(f ?x)"));
+        assert!(subsumes("?x", "?x:const"));
+        assert!(subsumes("?x:const", "?y:const"));
+        assert!(!subsumes("?x:const", "?y"));
+        assert!(subsumes("(f ?n!Symbol)", "(f 3)"));
+        assert!(!subsumes("(f ?n!Num)", "(f 3)"));
+        assert!(!subsumes("(f ?n!Symbol)", "(f (g 3))"));
+        assert!(subsumes("(f ?n!Symbol)", "(f ?m!Symbol)"));
+        assert!(!subsumes("(f ?n!Num)", "(f ?m!Symbol)"));
+    }
+
+    #[test]
+    #[should_panic = "does not support sequence var"]
+    fn unify_sequence_var() {
+        pat("(f ?xs...)").unify(&pat("(f a)"));
+    }
+
+    #[test]
+    fn critical_pairs() {
+        let assoc: Rewrite<S, ()> = rw!("assoc"; "(+ (+ ?a ?b) ?c)" => "(+ ?a (+ ?b ?c))");
+        let pairs: Vec<String> = assoc
+            .critical_pairs(&assoc)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            pairs,
+            vec!["(+ (+ (+ ?a' ?b') ?c') ?c) => (+ (+ ?a' ?b') (+ ?c' ?c)) / (+ (+ ?a' (+ ?b' ?c')) ?c)"]
+        );
+
+        // each occurrence of a shared subterm is its own position
+        let fg: Rewrite<S, ()> = rw!("fg"; "(+ (f ?x) (f ?x))" => "(g ?x)");
+        let f0: Rewrite<S, ()> = rw!("f0"; "(f 0)" => "0");
+        let pairs: Vec<String> = fg
+            .critical_pairs(&f0)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                "(+ (f 0) (f 0)) => (g 0) / (+ 0 (f 0))",
+                "(+ (f 0) (f 0)) => (g 0) / (+ (f 0) 0)",
+            ]
+        );
+        assert!(f0.critical_pairs(&fg).is_empty());
+
+        let searcher = AbsentSearcher {
+            searcher: Pattern::from(pat("(f ?x)")),
+            absent: Pattern::from(pat("(g ?x)")),
+        };
+        let custom: Rewrite<S, ()> = rw!("custom"; { searcher } => "(g ?x)");
+        assert!(custom.critical_pairs(&fg).is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use egg::{rewrite as rw, *};
-use rustc_hash::FxHashSet as HashSet;
+use std::ops::Range;
 
 define_language! {
     enum Lambda {
@@ -31,6 +31,27 @@ impl Lambda {
     }
 }
 
+impl BindingLanguage for Lambda {
+    fn var_name(&self) -> Option<Id> {
+        match self {
+            Lambda::Var(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn binder(&self) -> Option<(Id, Range<usize>)> {
+        match self {
+            Lambda::Let([v, _, _]) => Some((*v, 2..3)),
+            Lambda::Lambda([v, _]) | Lambda::Fix([v, _]) => Some((*v, 1..2)),
+            _ => None,
+        }
+    }
+
+    fn fresh_name(id: Id) -> Self {
+        Lambda::Symbol(format!("_{}", id).into())
+    }
+}
+
 type EGraph = egg::EGraph<Lambda, LambdaAnalysis>;
 
 #[derive(Default)]
@@ -38,7 +59,7 @@ struct LambdaAnalysis;
 
 #[derive(Debug)]
 struct Data {
-    free: HashSet<Id>,
+    free: FreeVars,
     constant: Option<(Lambda, PatternAst<Lambda>)>,
 }
 
@@ -62,37 +83,15 @@ fn eval(egraph: &EGraph, enode: &Lambda) -> Option<(Lambda, PatternAst<Lambda>)>
 impl Analysis<Lambda> for LambdaAnalysis {
     type Data = Data;
     fn merge(&mut self, to: &mut Data, from: Data) -> DidMerge {
-        let before_len = to.free.len();
-        // to.free.extend(from.free);
-        to.free.retain(|i| from.free.contains(i));
-        // compare lengths to see if I changed to or from
-        DidMerge(
-            before_len != to.free.len(),
-            to.free.len() != from.free.len(),
-        ) | merge_option(&mut to.constant, from.constant, |a, b| {
-            assert_eq!(a.0, b.0, "Merged non-equal constants");
-            DidMerge(false, false)
-        })
+        to.free.merge(from.free)
+            | merge_option(&mut to.constant, from.constant, |a, b| {
+                assert_eq!(a.0, b.0, "Merged non-equal constants");
+                DidMerge(false, false)
+            })
     }
 
     fn make(egraph: &mut EGraph, enode: &Lambda) -> Data {
-        let f = |i: &Id| egraph[*i].data.free.iter().cloned();
-        let mut free = HashSet::default();
-        match enode {
-            Lambda::Var(v) => {
-                free.insert(*v);
-            }
-            Lambda::Let([v, a, b]) => {
-                free.extend(f(b));
-                free.remove(v);
-                free.extend(f(a));
-            }
-            Lambda::Lambda([v, a]) | Lambda::Fix([v, a]) => {
-                free.extend(f(a));
-                free.remove(v);
-            }
-            _ => enode.for_each(|c| free.extend(&egraph[c].data.free)),
-        }
+        let free = FreeVars::make(egraph, enode);
         let constant = eval(egraph, enode);
         Data { constant, free }
     }
@@ -114,11 +113,20 @@ impl Analysis<Lambda> for LambdaAnalysis {
     }
 }
 
+impl BindingAnalysis<Lambda> for LambdaAnalysis {
+    fn free_vars(data: &Data) -> &FreeVars {
+        &data.free
+    }
+}
+
 fn var(s: &str) -> Var {
     s.parse().unwrap()
 }
 
-fn is_not_same_var(v1: Var, v2: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+fn is_not_same_var<N>(v1: Var, v2: Var) -> impl Fn(&mut egg::EGraph<Lambda, N>, Id, &Subst) -> bool
+where
+    N: Analysis<Lambda>,
+{
     move |egraph, _, subst| egraph.find(subst[v1]) != egraph.find(subst[v2])
 }
 
@@ -126,7 +134,8 @@ fn is_const(v: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
     move |egraph, _, subst| egraph[subst[v]].data.constant.is_some()
 }
 
-fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
+/// The rules that do not depend on how constants are folded.
+fn binding_rules<N: BindingAnalysis<Lambda> + 'static>() -> Vec<Rewrite<Lambda, N>> {
     vec![
         // open term rules
         rw!("if-true";  "(if  true ?then ?else)" => "?then"),
@@ -142,8 +151,6 @@ fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
         rw!("let-app";  "(let ?v ?e (app ?a ?b))" => "(app (let ?v ?e ?a) (let ?v ?e ?b))"),
         rw!("let-add";  "(let ?v ?e (+   ?a ?b))" => "(+   (let ?v ?e ?a) (let ?v ?e ?b))"),
         rw!("let-eq";   "(let ?v ?e (=   ?a ?b))" => "(=   (let ?v ?e ?a) (let ?v ?e ?b))"),
-        rw!("let-const";
-            "(let ?v ?e ?c)" => "?c" if is_const(var("?c"))),
         rw!("let-if";
             "(let ?v ?e (if ?cond ?then ?else))" =>
             "(if (let ?v ?e ?cond) (let ?v ?e ?then) (let ?v ?e ?else))"
@@ -155,7 +162,7 @@ fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
         rw!("let-lam-diff";
             "(let ?v1 ?e (lam ?v2 ?body))" =>
             { CaptureAvoid {
-                fresh: var("?fresh"), v2: var("?v2"), e: var("?e"),
+                fresh: var("?fresh"), name: var("?v2"), value: var("?e"),
                 if_not_free: "(lam ?v2 (let ?v1 ?e ?body))".parse().unwrap(),
                 if_free: "(lam ?fresh (let ?v1 ?e (let ?v2 (var ?fresh) ?body)))".parse().unwrap(),
             }}
@@ -163,37 +170,11 @@ fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
     ]
 }
 
-struct CaptureAvoid {
-    fresh: Var,
-    v2: Var,
-    e: Var,
-    if_not_free: Pattern<Lambda>,
-    if_free: Pattern<Lambda>,
-}
-
-impl Applier<Lambda, LambdaAnalysis> for CaptureAvoid {
-    fn apply_one(
-        &self,
-        egraph: &mut EGraph,
-        eclass: Id,
-        subst: &Subst,
-        searcher_ast: Option<&PatternAst<Lambda>>,
-        rule_name: Symbol,
-    ) -> Vec<Id> {
-        let e = subst[self.e];
-        let v2 = subst[self.v2];
-        let v2_free_in_e = egraph[e].data.free.contains(&v2);
-        if v2_free_in_e {
-            let mut subst = subst.clone();
-            let sym = Lambda::Symbol(format!("_{}", eclass).into());
-            subst.insert(self.fresh, egraph.add(sym));
-            self.if_free
-                .apply_one(egraph, eclass, &subst, searcher_ast, rule_name)
-        } else {
-            self.if_not_free
-                .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
-        }
-    }
+fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
+    let mut rules = binding_rules();
+    rules.push(rw!("let-const";
+        "(let ?v ?e ?c)" => "?c" if is_const(var("?c"))));
+    rules
 }
 
 egg::test_fn! {
@@ -401,3 +382,139 @@ fn lambda_ematching_bench() {
 
     egg::test::bench_egraph("lambda", rules(), exprs, extra_patterns);
 }
+
+/// Only tracks free variables, so constants are folded by rules that bind
+/// the data of `Num` and `Bool` leaves instead of by the analysis.
+#[derive(Default)]
+struct LeafAnalysis;
+
+type LeafEGraph = egg::EGraph<Lambda, LeafAnalysis>;
+
+impl Analysis<Lambda> for LeafAnalysis {
+    type Data = FreeVars;
+    fn merge(&mut self, to: &mut FreeVars, from: FreeVars) -> DidMerge {
+        to.merge(from)
+    }
+
+    fn make(egraph: &mut LeafEGraph, enode: &Lambda) -> FreeVars {
+        FreeVars::make(egraph, enode)
+    }
+}
+
+impl BindingAnalysis<Lambda> for LeafAnalysis {
+    fn free_vars(data: &FreeVars) -> &FreeVars {
+        data
+    }
+}
+
+/// Folds `+` and `=` over the constants bound by `?a` and `?b`.
+enum Fold {
+    Add,
+    Eq,
+}
+
+fn constant(egraph: &LeafEGraph, subst: &Subst, v: &str) -> Option<Lambda> {
+    let v = var(v);
+    let num = subst.leaf::<i32, _, _>(egraph, v).map(|n| Lambda::Num(*n));
+    num.or_else(|| {
+        subst
+            .leaf::<bool, _, _>(egraph, v)
+            .map(|b| Lambda::Bool(*b))
+    })
+}
+
+impl Applier<Lambda, LeafAnalysis> for Fold {
+    fn apply_one(
+        &self,
+        egraph: &mut LeafEGraph,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&PatternAst<Lambda>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        let (a, b) = match (constant(egraph, subst, "?a"), constant(egraph, subst, "?b")) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return vec![],
+        };
+        let folded = match (self, &a, &b) {
+            (Fold::Add, Lambda::Num(x), Lambda::Num(y)) => match x.checked_add(*y) {
+                Some(n) => Lambda::Num(n),
+                None => return vec![],
+            },
+            (Fold::Eq, _, _) => Lambda::Bool(a == b),
+            _ => return vec![],
+        };
+        let op = match self {
+            Fold::Add => "+",
+            Fold::Eq => "=",
+        };
+        let did_union = if egraph.are_explanations_enabled() {
+            let from: PatternAst<Lambda> = format!("({} {} {})", op, a, b).parse().unwrap();
+            let to: PatternAst<Lambda> = folded.to_string().parse().unwrap();
+            egraph
+                .union_instantiations(&from, &to, &Default::default(), rule_name)
+                .1
+        } else {
+            let id = egraph.add(folded);
+            egraph.union(eclass, id)
+        };
+        if did_union {
+            vec![eclass]
+        } else {
+            vec![]
+        }
+    }
+}
+
+fn leaf_rules() -> Vec<Rewrite<Lambda, LeafAnalysis>> {
+    let mut rules = binding_rules();
+    rules.extend(vec![
+        rw!("add-fold";  "(+ ?a!Num ?b!Num)"   => { Fold::Add }),
+        rw!("eq-num";    "(= ?a!Num ?b!Num)"   => { Fold::Eq }),
+        rw!("eq-bool";   "(= ?a!Bool ?b!Bool)" => { Fold::Eq }),
+        rw!("let-num";  "(let ?v ?e ?c!Num)"  => "?c"),
+        rw!("let-bool"; "(let ?v ?e ?c!Bool)" => "?c"),
+    ]);
+    rules
+}
+
+egg::test_fn! {
+    lambda_leaf_under, leaf_rules(),
+    "(lam x (+ 4
+               (app (lam y (var y))
+                    4)))"
+    =>
+    "(lam x 8))",
+}
+
+egg::test_fn! {
+    lambda_leaf_let_simple, leaf_rules(),
+    "(let x 0
+     (let y 1
+     (+ (var x) (var y))))"
+    =>
+    "1",
+}
+
+egg::test_fn! {
+    lambda_leaf_if, leaf_rules(),
+    "(let zeroone (lam x
+        (if (= (var x) 0)
+            0
+            1))
+        (+ (app (var zeroone) 0)
+        (app (var zeroone) 10)))"
+    =>
+    "1",
+}
+
+egg::test_fn! {
+    #[should_panic(expected = "Could not prove goal 0")]
+    lambda_leaf_closure_not_seven, leaf_rules(),
+    "(let five 5
+     (let add-five (lam x (+ (var x) (var five)))
+     (let five 6
+     (app (var add-five) 1))))"
+    =>
+    "7"
+}